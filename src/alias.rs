@@ -0,0 +1,136 @@
+//! Alias expansion, which replaces the first word of simple commands before
+//! the command text is parsed.
+
+use std::collections::BTreeMap;
+
+use parser_state::Lexer;
+
+use crate::parser::assignment;
+use crate::scanner::{Scanner, TokenTag};
+
+/// Replaces aliases in the command words of a command text, which is scanned
+/// with extended pattern groups if `is_extglob`. Returns the text unchanged
+/// if it can't be scanned, leaving the error for the parser to report.
+pub fn expand_aliases(aliases: &BTreeMap<String, String>, text: &str, is_extglob: bool) -> String {
+    if aliases.is_empty() {
+        return text.to_string();
+    }
+    let mut active = Vec::new();
+    expand(aliases, text, is_extglob, &mut active).unwrap_or_else(|_| text.to_string())
+}
+
+/// Determines if a string can name an alias.
+pub fn is_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains(|c: char| {
+            c.is_whitespace()
+                || matches!(
+                    c,
+                    '/' | '$'
+                        | '`'
+                        | '='
+                        | '\''
+                        | '"'
+                        | '\\'
+                        | '|'
+                        | '&'
+                        | ';'
+                        | '<'
+                        | '>'
+                        | '('
+                        | ')'
+                )
+        })
+}
+
+/// Expands aliases in a text. Aliases in `active` are already being expanded,
+/// so they're left alone to stop the expansion recursing.
+fn expand(
+    aliases: &BTreeMap<String, String>,
+    text: &str,
+    is_extglob: bool,
+    active: &mut Vec<String>,
+) -> anyhow::Result<String> {
+    let mut scanner = Scanner::new(text).with_extglob(is_extglob);
+    let mut expanded = String::new();
+
+    // Byte offset of the text not yet copied to `expanded`.
+    let mut copied = 0;
+
+    let mut is_command_position = true;
+
+    // Number of words left before a case command's patterns, i.e. its
+    // subject and `in`, and whether the next words are a case item's
+    // patterns. Neither are commands, so they're never aliases.
+    let mut case_words = 0;
+    let mut is_pattern = false;
+
+    loop {
+        let token = scanner.next_token()?;
+        is_command_position = match token.tag {
+            TokenTag::EndOfCommand => break,
+            TokenTag::Word if case_words > 0 => {
+                case_words -= 1;
+                is_pattern = case_words == 0;
+                false
+            }
+            TokenTag::Word if is_pattern => {
+                is_pattern = token.lexeme != "esac";
+                false
+            }
+            TokenTag::LeftParen | TokenTag::Newline | TokenTag::Pipe if is_pattern => false,
+            TokenTag::RightParen if is_pattern => {
+                is_pattern = false;
+                true
+            }
+            TokenTag::DoubleSemicolon | TokenTag::CaseContinue | TokenTag::CaseFallThrough => {
+                is_pattern = true;
+                false
+            }
+            TokenTag::Word if is_command_position => match aliases.get(&token.lexeme) {
+                Some(value) if !active.contains(&token.lexeme) => {
+                    active.push(token.lexeme.clone());
+                    let value = expand(aliases, value, is_extglob, active)?;
+                    active.pop();
+
+                    expanded.push_str(&text[copied..token.span.start]);
+                    expanded.push_str(&value);
+                    copied = token.span.end;
+
+                    // A trailing blank means the next word is expanded too.
+                    value.ends_with([' ', '\t'])
+                }
+                _ if token.lexeme == "case" => {
+                    case_words = 2;
+                    false
+                }
+                // Assignments before a command's name leave it in command
+                // position, e.g. in `X=1 ll`.
+                _ if token.word.as_ref().and_then(assignment).is_some() => true,
+                _ => is_command_prefix(&token.lexeme),
+            },
+            // Only a `(` where a command could start opens a subshell.
+            TokenTag::LeftParen => is_command_position,
+            TokenTag::Ampersand
+            | TokenTag::And
+            | TokenTag::Newline
+            | TokenTag::Or
+            | TokenTag::Pipe
+            | TokenTag::RightParen
+            | TokenTag::Semicolon => true,
+            _ => false,
+        };
+    }
+
+    expanded.push_str(&text[copied..]);
+    Ok(expanded)
+}
+
+/// Determines if a word in command position is a reserved word which is
+/// followed by another command.
+fn is_command_prefix(word: &str) -> bool {
+    matches!(
+        word,
+        "!" | "{" | "do" | "elif" | "else" | "if" | "then" | "until" | "while"
+    )
+}
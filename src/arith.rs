@@ -0,0 +1,452 @@
+//! Evaluation of arithmetic expressions, e.g. `(( i++ ))` or `$(( i + 1 ))`.
+
+use anyhow::anyhow;
+
+use crate::error::unbound_variable;
+use crate::shell::Shell;
+
+/// How deeply variables whose values are expressions may refer to others.
+const MAX_DEPTH: usize = 16;
+
+/// Operators ordered so that longer operators come before their prefixes.
+const OPERATORS: [&str; 40] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "!", "~", "&", "^", "|",
+    "?", ":", "=", ",", "(", ")", "$",
+];
+
+/// Binary operators from lowest to highest precedence, after `&&`.
+const BINARY_LEVELS: [&[&str]; 8] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+/// Assignment operators.
+const ASSIGNMENT_OPERATORS: [&str; 11] = [
+    "=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+/// Evaluates an arithmetic expression. Variables are read from and assigned
+/// to the given shell.
+pub fn evaluate(shell: &mut Shell, expression: &str) -> anyhow::Result<i64> {
+    evaluate_nested(shell, expression, 0)
+}
+
+fn evaluate_nested(shell: &mut Shell, expression: &str, depth: usize) -> anyhow::Result<i64> {
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+
+    let mut evaluator = Evaluator {
+        shell,
+        expression,
+        tokens,
+        position: 0,
+        skipping: 0,
+        depth,
+    };
+    let value = evaluator.comma()?;
+    if let Some(token) = evaluator.tokens.get(evaluator.position) {
+        Err(evaluator.error(&format!(
+            "syntax error in expression (error token is \"{token}\")"
+        )))?
+    }
+    Ok(value)
+}
+
+/// A token in an arithmetic expression.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{}", n),
+            Self::Name(name) => write!(f, "{}", name),
+            Self::Operator(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+/// Splits an expression into tokens.
+fn tokenize(expression: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        let len = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '#' | '@' | '_')))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(parse_number(&rest[..len])?));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_string()));
+            len
+        } else if let Some(name) = rest.strip_prefix("${") {
+            let len = name
+                .find('}')
+                .ok_or_else(|| anyhow!("{expression}: unclosed parameter expansion"))?;
+            tokens.push(Token::Name(name[..len].to_string()));
+            len + 3
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            // A `$` before a name is ignored, as names are already variable
+            // references. Otherwise it names a special parameter, e.g. `$1`.
+            if *op == "$" {
+                match rest[1..].chars().next() {
+                    Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+                    Some(c) if !c.is_whitespace() => {
+                        tokens.push(Token::Name(c.to_string()));
+                        rest = &rest[1 + c.len_utf8()..];
+                        rest = rest.trim_start();
+                        continue;
+                    }
+                    _ => Err(anyhow!("{expression}: syntax error: operand expected"))?,
+                }
+            } else {
+                tokens.push(Token::Operator(op));
+            }
+            op.len()
+        } else {
+            Err(anyhow!(
+                "{expression}: syntax error: invalid arithmetic operator (error token is \"{rest}\")"
+            ))?
+        };
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Parses an integer constant, which may be hexadecimal (`0x1f`), octal
+/// (`017`) or in a given base (`2#101`).
+fn parse_number(text: &str) -> anyhow::Result<i64> {
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        match base.parse::<u32>() {
+            Ok(base) if (2..=64).contains(&base) => (base, digits),
+            _ => Err(anyhow!("{text}: invalid arithmetic base"))?,
+        }
+    } else if let Some(digits) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        (16, digits)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => u32::MAX,
+        };
+        if digit >= base {
+            Err(anyhow!(
+                "{text}: value too great for base (error token is \"{text}\")"
+            ))?
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(value)
+}
+
+/// Parses integers from text, which is either a number or, for variables,
+/// an expression. An empty value is zero.
+fn parse_value(shell: &mut Shell, text: &str, depth: usize) -> anyhow::Result<i64> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(0);
+    }
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, text.strip_prefix('+').unwrap_or(text)),
+    };
+    if let Ok(value) = parse_number(digits) {
+        return Ok(sign * value);
+    }
+    if depth >= MAX_DEPTH {
+        Err(anyhow!("{text}: expression recursion level exceeded"))?
+    }
+    evaluate_nested(shell, text, depth + 1)
+}
+
+/// A recursive descent evaluator for a tokenized expression.
+struct Evaluator<'a> {
+    shell: &'a mut Shell,
+    expression: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+
+    /// Greater than zero while evaluating an operand whose value is unused,
+    /// e.g. `x++` in `0 && x++`. Nothing is assigned while skipping.
+    skipping: usize,
+
+    /// How many variables deep this expression is.
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    /// Evaluates expressions separated by commas.
+    fn comma(&mut self) -> anyhow::Result<i64> {
+        let mut value = self.assignment()?;
+        while self.matches(",") {
+            value = self.assignment()?;
+        }
+        Ok(value)
+    }
+
+    /// Evaluates an assignment or a conditional expression.
+    fn assignment(&mut self) -> anyhow::Result<i64> {
+        let operator = match self.tokens.get(self.position + 1) {
+            Some(Token::Operator(op)) if ASSIGNMENT_OPERATORS.contains(op) => *op,
+            _ => return self.conditional(),
+        };
+        let name = match self.tokens.get(self.position) {
+            Some(Token::Name(name)) => name.clone(),
+            _ => return self.conditional(),
+        };
+        self.position += 2;
+
+        let right = self.assignment()?;
+        let value = match operator.strip_suffix('=') {
+            Some("") => right,
+            Some(op) => {
+                let left = self.variable(&name)?;
+                self.apply(op, left, right)?
+            }
+            None => unreachable!(),
+        };
+        self.assign(&name, value)?;
+        Ok(value)
+    }
+
+    /// Evaluates a conditional expression, e.g. `a ? b : c`.
+    fn conditional(&mut self) -> anyhow::Result<i64> {
+        let condition = self.logical_or()?;
+        if !self.matches("?") {
+            return Ok(condition);
+        }
+
+        let if_true = self.skip_if(condition == 0, Self::assignment)?;
+        self.expect(":")?;
+        let if_false = self.skip_if(condition != 0, Self::assignment)?;
+
+        Ok(if condition != 0 { if_true } else { if_false })
+    }
+
+    fn logical_or(&mut self) -> anyhow::Result<i64> {
+        let mut value = self.logical_and()?;
+        while self.matches("||") {
+            let right = self.skip_if(value != 0, Self::logical_and)?;
+            value = (value != 0 || right != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    fn logical_and(&mut self) -> anyhow::Result<i64> {
+        let mut value = self.binary(0)?;
+        while self.matches("&&") {
+            let right = self.skip_if(value == 0, |e| e.binary(0))?;
+            value = (value != 0 && right != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    /// Evaluates left associative binary operators at a precedence level.
+    fn binary(&mut self, level: usize) -> anyhow::Result<i64> {
+        let Some(operators) = BINARY_LEVELS.get(level) else {
+            return self.power();
+        };
+
+        let mut value = self.binary(level + 1)?;
+        while let Some(op) = operators.iter().find(|op| self.check(op)) {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            value = self.apply(op, value, right)?;
+        }
+        Ok(value)
+    }
+
+    /// Evaluates the right associative `**` operator.
+    fn power(&mut self) -> anyhow::Result<i64> {
+        let base = self.unary()?;
+        if !self.matches("**") {
+            return Ok(base);
+        }
+        let exponent = self.power()?;
+        self.apply("**", base, exponent)
+    }
+
+    fn unary(&mut self) -> anyhow::Result<i64> {
+        if self.matches("!") {
+            Ok((self.unary()? == 0) as i64)
+        } else if self.matches("~") {
+            Ok(!self.unary()?)
+        } else if self.matches("-") {
+            Ok(self.unary()?.wrapping_neg())
+        } else if self.matches("+") {
+            self.unary()
+        } else if self.check("++") || self.check("--") {
+            let delta = if self.check("++") { 1 } else { -1 };
+            self.position += 1;
+            let name = self.name()?;
+            let value = self.variable(&name)?.wrapping_add(delta);
+            self.assign(&name, value)?;
+            Ok(value)
+        } else {
+            self.postfix()
+        }
+    }
+
+    fn postfix(&mut self) -> anyhow::Result<i64> {
+        match self.tokens.get(self.position).cloned() {
+            Some(Token::Number(value)) => {
+                self.position += 1;
+                Ok(value)
+            }
+            Some(Token::Name(name)) => {
+                self.position += 1;
+                let value = self.variable(&name)?;
+                if self.matches("++") {
+                    self.assign(&name, value.wrapping_add(1))?;
+                } else if self.matches("--") {
+                    self.assign(&name, value.wrapping_sub(1))?;
+                }
+                Ok(value)
+            }
+            Some(Token::Operator("(")) => {
+                self.position += 1;
+                let value = self.comma()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            Some(token) => Err(self.error(&format!(
+                "syntax error: operand expected (error token is \"{token}\")"
+            ))),
+            None => Err(self.error("syntax error: operand expected")),
+        }
+    }
+
+    /// Applies a binary operator.
+    fn apply(&self, op: &str, left: i64, right: i64) -> anyhow::Result<i64> {
+        let value = match op {
+            "|" => left | right,
+            "^" => left ^ right,
+            "&" => left & right,
+            "==" => (left == right) as i64,
+            "!=" => (left != right) as i64,
+            "<" => (left < right) as i64,
+            "<=" => (left <= right) as i64,
+            ">" => (left > right) as i64,
+            ">=" => (left >= right) as i64,
+            "<<" => left.wrapping_shl(right as u32),
+            ">>" => left.wrapping_shr(right as u32),
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "*" => left.wrapping_mul(right),
+            "/" | "%" if right == 0 => {
+                if self.skipping > 0 {
+                    0
+                } else {
+                    Err(self.error("division by 0"))?
+                }
+            }
+            "/" => left.wrapping_div(right),
+            "%" => left.wrapping_rem(right),
+            "**" if right < 0 => Err(self.error("exponent less than 0"))?,
+            "**" => left.wrapping_pow(right.min(u32::MAX as i64) as u32),
+            _ => unreachable!("unknown operator {op}"),
+        };
+        Ok(value)
+    }
+
+    /// Evaluates an operand, skipping assignments in it if `skip` is true.
+    fn skip_if<F>(&mut self, skip: bool, operand: F) -> anyhow::Result<i64>
+    where
+        F: FnOnce(&mut Self) -> anyhow::Result<i64>,
+    {
+        self.skipping += usize::from(skip);
+        let value = operand(self);
+        self.skipping -= usize::from(skip);
+        value
+    }
+
+    /// Gets the value of a variable.
+    fn variable(&mut self, name: &str) -> anyhow::Result<i64> {
+        let value = match self.shell.get_variable(name) {
+            Some(value) => value,
+            None if self.shell.options.nounset && self.skipping == 0 => {
+                return Err(unbound_variable(name));
+            }
+            None => String::new(),
+        };
+        parse_value(self.shell, &value, self.depth)
+    }
+
+    /// Assigns a variable, unless skipping.
+    fn assign(&mut self, name: &str, value: i64) -> anyhow::Result<()> {
+        if self.skipping == 0 {
+            self.shell.set_variable(name, value.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Consumes a name token.
+    fn name(&mut self) -> anyhow::Result<String> {
+        match self.tokens.get(self.position) {
+            Some(Token::Name(name)) => {
+                let name = name.clone();
+                self.position += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("syntax error: variable name expected")),
+        }
+    }
+
+    /// Determines if the current token is the given operator.
+    fn check(&self, op: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some(Token::Operator(o)) if *o == op)
+    }
+
+    /// Consumes the given operator if it's the current token.
+    fn matches(&mut self, op: &str) -> bool {
+        let is_match = self.check(op);
+        if is_match {
+            self.position += 1;
+        }
+        is_match
+    }
+
+    /// Consumes the given operator or fails.
+    fn expect(&mut self, op: &str) -> anyhow::Result<()> {
+        if self.matches(op) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("syntax error: `{op}` expected")))
+        }
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow!("{}: {}", self.expression.trim(), message)
+    }
+}
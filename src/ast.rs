@@ -1,47 +1,859 @@
 //! Abstract syntax tree types for a command.
 
+use std::fmt;
+use std::rc::Rc;
+
+use anyhow::anyhow;
+
+/// A sequence of and-or lists separated by `;` or newlines.
+pub type List = Vec<AndOr>;
+
+/// Pipelines joined by `&&` and `||` operators, e.g. `a && b || c`.
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+
+    /// The list ends with `&`, so it runs in the background and the shell
+    /// doesn't wait for it.
+    pub is_async: bool,
+}
+
+/// An operator joining two pipelines in an and-or list.
+#[derive(Clone, Copy)]
+pub enum Connector {
+    /// The `&&` operator.
+    And,
+
+    /// The `||` operator.
+    Or,
+}
+
+/// Commands joined by `|` operators, possibly negated with `!`.
+pub struct Pipeline {
+    pub is_negated: bool,
+    pub commands: Vec<Command>,
+}
+
 /// A shell command.
 pub enum Command {
     BuiltIn(BuiltInCommand),
     External(ExternalCommand),
+    Arithmetic(ArithmeticCommand),
+    ArithmeticFor(ArithmeticForCommand),
+    Case(CaseCommand),
+    Conditional(ConditionalCommand),
+    For(ForCommand),
+    FunctionDefinition(FunctionDefinition),
+    Group(GroupCommand),
+    If(IfCommand),
+    Subshell(SubshellCommand),
+    While(WhileCommand),
 }
 
 /// Contents of a built-in command.
 pub struct BuiltInCommand {
+    /// Variables set while the built-in runs.
+    pub assignments: Vec<Assignment>,
+
     pub built_in: BuiltIn,
-    pub redirection: Redirection,
+    pub redirections: Vec<Redirection>,
+
+    /// Line number the command starts on, for `$LINENO`.
+    pub line: usize,
 }
 
 /// Contents of an external command.
 pub struct ExternalCommand {
-    pub args: Vec<String>,
-    pub redirection: Redirection,
+    /// Variables set in the command's environment, or in the shell if there
+    /// are no args.
+    pub assignments: Vec<Assignment>,
+
+    pub args: Vec<Word>,
+    pub redirections: Vec<Redirection>,
+
+    /// Line number the command starts on, for `$LINENO`.
+    pub line: usize,
+}
+
+/// An assignment word, e.g. `name=value`, `name[1]=value` or
+/// `name=(a b c)`.
+pub struct Assignment {
+    pub name: String,
+
+    /// The subscript of the array element being assigned, if any.
+    pub subscript: Option<Word>,
+
+    /// The value, which is a single [`WordPart::Array`] part for an array
+    /// assignment.
+    pub value: Word,
+}
+
+/// An arithmetic command, e.g. `(( i++ ))`.
+pub struct ArithmeticCommand {
+    pub expression: String,
+    pub redirections: Vec<Redirection>,
+}
+
+/// A C-style for loop, e.g. `for (( i = 0; i < n; i++ )); do ...; done`.
+pub struct ArithmeticForCommand {
+    pub init: String,
+    pub condition: String,
+    pub update: String,
+    pub body: List,
+    pub redirections: Vec<Redirection>,
+}
+
+/// A case command, e.g. `case $x in a|b) ...;; esac`.
+pub struct CaseCommand {
+    pub word: Word,
+    pub items: Vec<CaseItem>,
+    pub redirections: Vec<Redirection>,
+}
+
+/// A list of commands in a case command which runs if the word matches one
+/// of its patterns.
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: List,
+    pub terminator: CaseTerminator,
+}
+
+/// What happens after a case item's commands run.
+#[derive(Clone, Copy)]
+pub enum CaseTerminator {
+    /// `;;` ends the case command.
+    Break,
+
+    /// `;&` runs the next item's commands without testing its patterns.
+    FallThrough,
+
+    /// `;;&` tests the patterns of the following items.
+    Continue,
+}
+
+/// A conditional command, e.g. `[[ -f $file && $name == *.txt ]]`.
+pub struct ConditionalCommand {
+    pub expression: ConditionalExpression,
+    pub redirections: Vec<Redirection>,
+}
+
+/// An expression in a conditional command. Its words aren't split into
+/// fields or expanded into pathnames.
+pub enum ConditionalExpression {
+    /// A word, which is true if it isn't empty.
+    Word(Word),
+
+    /// A test of one operand, e.g. `-f $file`.
+    Unary(String, Word),
+
+    /// A comparison of two operands, e.g. `$name == *.txt`.
+    Binary(Word, String, Word),
+
+    Not(Box<ConditionalExpression>),
+    And(Box<ConditionalExpression>, Box<ConditionalExpression>),
+    Or(Box<ConditionalExpression>, Box<ConditionalExpression>),
+
+    /// An expression in parentheses.
+    Group(Box<ConditionalExpression>),
+}
+
+/// A for loop over a word list, e.g. `for name in words; do ...; done`.
+pub struct ForCommand {
+    pub name: String,
+
+    /// The words to iterate over. `None` iterates over the positional
+    /// parameters.
+    pub words: Option<Vec<Word>>,
+
+    pub body: List,
+    pub redirections: Vec<Redirection>,
+}
+
+/// A function definition, e.g. `name() { ...; }`.
+pub struct FunctionDefinition {
+    pub name: String,
+
+    /// A compound command, which is shared with the shell's function table
+    /// once the definition is evaluated.
+    pub body: Rc<Command>,
+}
+
+/// A brace group, e.g. `{ ...; }`, which runs in the current shell.
+pub struct GroupCommand {
+    pub body: List,
+    pub redirections: Vec<Redirection>,
+}
+
+/// An if command with any number of `elif` branches.
+pub struct IfCommand {
+    /// Pairs of conditions and the bodies which run when they succeed.
+    pub branches: Vec<(List, List)>,
+
+    pub else_body: Option<List>,
+    pub redirections: Vec<Redirection>,
+}
+
+/// A subshell, e.g. `( ... )`, which runs in a child process so changes to
+/// the shell's state don't outlast it.
+pub struct SubshellCommand {
+    pub body: List,
+    pub redirections: Vec<Redirection>,
+}
+
+/// A while or until loop.
+pub struct WhileCommand {
+    pub condition: List,
+    pub body: List,
+
+    /// If true, the loop runs until the condition succeeds.
+    pub is_until: bool,
+
+    pub redirections: Vec<Redirection>,
 }
 
 /// A shell command.
 #[derive(Debug)]
 pub enum BuiltIn {
+    /// Defines or prints aliases.
+    Alias(Vec<Word>),
+
+    /// Exits from a number of enclosing loops.
+    Break(Option<Word>),
+
     /// Changes the working directory to a given path.
-    Cd(String),
+    Cd(Word),
+
+    /// Resumes the next iteration of an enclosing loop.
+    Continue(Option<Word>),
+
+    /// Declares variables and sets their attributes.
+    Declare(Vec<Word>),
+
+    /// Another name for `source`.
+    Dot(Vec<Word>),
+
+    /// Another name for `test`, whose last argument must be `]`.
+    Bracket(Vec<Word>),
 
     /// Echos back user input.
-    Echo(Vec<String>),
+    Echo(Vec<Word>),
 
     /// Exits the shell with a return code.
-    Exit(i32),
+    Exit(Option<Word>),
+
+    /// Marks variables to be passed to child processes.
+    Export(Vec<Word>),
 
     /// Shows history with an optional limit.
-    History(Option<usize>),
+    History(Option<Word>),
+
+    /// Declares variables local to the function being evaluated.
+    Local(Vec<Word>),
+
+    /// Formats and prints its arguments.
+    Printf(Vec<Word>),
 
     /// Prints the working directory.
     Pwd,
 
+    /// Reads a line of input into variables.
+    Read(Vec<Word>),
+
+    /// Stops variables from being changed.
+    Readonly(Vec<Word>),
+
+    /// Returns from a function with an optional exit status.
+    Return(Option<Word>),
+
+    /// Sets the positional parameters, or prints the shell's variables.
+    Set(Vec<Word>),
+
+    /// Shifts the positional parameters to the left.
+    Shift(Option<Word>),
+
+    /// Changes or prints the options which aren't changed with `set`.
+    Shopt(Vec<Word>),
+
+    /// Runs the commands in a file in the current shell.
+    Source(Vec<Word>),
+
+    /// Evaluates a condition, e.g. if a file exists.
+    Test(Vec<Word>),
+
     /// Displays the type of command.
-    Type(String),
+    Type(Word),
+
+    /// Another name for `declare`.
+    Typeset(Vec<Word>),
+
+    /// Removes aliases.
+    Unalias(Vec<Word>),
+
+    /// Removes variables or functions.
+    Unset(Vec<Word>),
+}
+
+/// Names of the built-ins.
+pub const BUILT_INS: [&str; 26] = [
+    ".", "[", "alias", "break", "cd", "continue", "declare", "echo", "exit", "export", "history",
+    "local", "printf", "pwd", "read", "readonly", "return", "set", "shift", "shopt", "source",
+    "test", "type", "typeset", "unalias", "unset",
+];
+
+/// Names of the special built-ins, which are found before functions of the
+/// same name. Other built-ins can be overridden by functions.
+pub const SPECIAL_BUILT_INS: [&str; 10] = [
+    ".", "break", "continue", "exit", "export", "readonly", "return", "set", "shift", "unset",
+];
+
+impl BuiltIn {
+    /// Creates the built-in with a given name and argument words. Returns
+    /// `None` if there's no built-in with the name.
+    pub fn new(name: &str, mut args: Vec<Word>) -> anyhow::Result<Option<BuiltIn>> {
+        let mut at_most_one = || match args.len() {
+            0 | 1 => Ok(args.pop()),
+            _ => Err(anyhow!("{name}: too many arguments")),
+        };
+        let built_in = match name {
+            "." => BuiltIn::Dot(args),
+            "[" => BuiltIn::Bracket(args),
+            "alias" => BuiltIn::Alias(args),
+            "break" => BuiltIn::Break(at_most_one()?),
+            "cd" => BuiltIn::Cd(at_most_one()?.ok_or_else(|| anyhow!("cd: missing directory"))?),
+            "continue" => BuiltIn::Continue(at_most_one()?),
+            "declare" => BuiltIn::Declare(args),
+            "echo" => BuiltIn::Echo(args),
+            "exit" => BuiltIn::Exit(at_most_one()?),
+            "export" => BuiltIn::Export(args),
+            "history" => BuiltIn::History(at_most_one()?),
+            "local" => BuiltIn::Local(args),
+            "printf" => BuiltIn::Printf(args),
+            "pwd" => match args.is_empty() {
+                true => BuiltIn::Pwd,
+                false => return Err(anyhow!("pwd: too many arguments")),
+            },
+            "read" => BuiltIn::Read(args),
+            "readonly" => BuiltIn::Readonly(args),
+            "return" => BuiltIn::Return(at_most_one()?),
+            "set" => BuiltIn::Set(args),
+            "shift" => BuiltIn::Shift(at_most_one()?),
+            "shopt" => BuiltIn::Shopt(args),
+            "source" => BuiltIn::Source(args),
+            "test" => BuiltIn::Test(args),
+            "type" => BuiltIn::Type(at_most_one()?.ok_or_else(|| anyhow!("type: missing name"))?),
+            "typeset" => BuiltIn::Typeset(args),
+            "unalias" => BuiltIn::Unalias(args),
+            "unset" => BuiltIn::Unset(args),
+            _ => return Ok(None),
+        };
+        Ok(Some(built_in))
+    }
+
+    /// Gets the built-in's name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltIn::Alias(_) => "alias",
+            BuiltIn::Break(_) => "break",
+            BuiltIn::Cd(_) => "cd",
+            BuiltIn::Continue(_) => "continue",
+            BuiltIn::Declare(_) => "declare",
+            BuiltIn::Dot(_) => ".",
+            BuiltIn::Bracket(_) => "[",
+            BuiltIn::Echo(_) => "echo",
+            BuiltIn::Exit(_) => "exit",
+            BuiltIn::Export(_) => "export",
+            BuiltIn::History(_) => "history",
+            BuiltIn::Local(_) => "local",
+            BuiltIn::Printf(_) => "printf",
+            BuiltIn::Pwd => "pwd",
+            BuiltIn::Read(_) => "read",
+            BuiltIn::Readonly(_) => "readonly",
+            BuiltIn::Return(_) => "return",
+            BuiltIn::Set(_) => "set",
+            BuiltIn::Shift(_) => "shift",
+            BuiltIn::Shopt(_) => "shopt",
+            BuiltIn::Source(_) => "source",
+            BuiltIn::Test(_) => "test",
+            BuiltIn::Type(_) => "type",
+            BuiltIn::Typeset(_) => "typeset",
+            BuiltIn::Unalias(_) => "unalias",
+            BuiltIn::Unset(_) => "unset",
+        }
+    }
+
+    /// Gets the built-in's argument words.
+    pub fn args(&self) -> Vec<&Word> {
+        match self {
+            BuiltIn::Alias(args)
+            | BuiltIn::Declare(args)
+            | BuiltIn::Dot(args)
+            | BuiltIn::Bracket(args)
+            | BuiltIn::Echo(args)
+            | BuiltIn::Export(args)
+            | BuiltIn::Local(args)
+            | BuiltIn::Printf(args)
+            | BuiltIn::Read(args)
+            | BuiltIn::Readonly(args)
+            | BuiltIn::Set(args)
+            | BuiltIn::Shopt(args)
+            | BuiltIn::Source(args)
+            | BuiltIn::Test(args)
+            | BuiltIn::Typeset(args)
+            | BuiltIn::Unalias(args)
+            | BuiltIn::Unset(args) => args.iter().collect(),
+            BuiltIn::Break(arg)
+            | BuiltIn::Continue(arg)
+            | BuiltIn::Exit(arg)
+            | BuiltIn::History(arg)
+            | BuiltIn::Return(arg)
+            | BuiltIn::Shift(arg) => arg.iter().collect(),
+            BuiltIn::Cd(arg) | BuiltIn::Type(arg) => vec![arg],
+            BuiltIn::Pwd => vec![],
+        }
+    }
 }
 
+/// A redirection of one of a command's standard streams to a file.
+#[allow(clippy::enum_variant_names)]
 pub enum Redirection {
-    None,
-    StdOut { filename: String, is_append: bool },
-    StdErr { filename: String, is_append: bool },
+    StdIn { filename: Word },
+    StdOut { filename: Word, is_append: bool },
+    StdErr { filename: Word, is_append: bool },
+}
+
+/// A word in a command before expansion.
+#[derive(Clone, Debug, Default)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+impl Word {
+    /// Creates a word of quoted text, which expands to exactly that text.
+    pub fn quoted(text: &str) -> Word {
+        Word {
+            parts: vec![WordPart::Quoted(text.to_string())],
+        }
+    }
+}
+
+/// A piece of a word which is expanded in its own way.
+#[derive(Clone, Debug)]
+pub enum WordPart {
+    /// Unquoted text.
+    Literal(String),
+
+    /// Text quoted with single quotes or a backslash.
+    Quoted(String),
+
+    /// Parts inside double quotes.
+    DoubleQuoted(Vec<WordPart>),
+
+    /// A parameter expansion, e.g. `$name` or `${name}`.
+    Parameter(String),
+
+    /// An arithmetic expansion, e.g. `$(( i + 1 ))`.
+    Arithmetic(String),
+
+    /// A command substitution, e.g. `$(date)` or `` `date` ``, with the text
+    /// of its commands.
+    Command(String),
+
+    /// A parameter expansion in braces with a subscript or an operator,
+    /// e.g. `${#name}` or `${arr[@]:1:2}`.
+    Braced(Box<ParameterExpansion>),
+
+    /// The elements of an array assignment, e.g. `(a b c)` in `arr=(a b c)`.
+    Array(Vec<Word>),
+}
+
+/// A parameter expansion in braces, e.g. `${arr[1]}`.
+#[derive(Clone, Debug)]
+pub struct ParameterExpansion {
+    pub name: String,
+    pub subscript: Option<Subscript>,
+    pub operator: ParameterOperator,
+}
+
+/// The subscript of an array in a parameter expansion.
+#[derive(Clone, Debug)]
+pub enum Subscript {
+    /// `@`, which expands to every element, each as a separate field when
+    /// quoted.
+    At,
+
+    /// `*`, which expands to every element joined into a single field when
+    /// quoted.
+    Star,
+
+    /// A single element's index or key.
+    Element(Word),
+}
+
+/// What a parameter expansion in braces expands to.
+#[derive(Clone, Debug)]
+pub enum ParameterOperator {
+    /// The value, e.g. `${arr[1]}`.
+    Value,
+
+    /// The length of the value, or the number of elements, e.g. `${#name}`
+    /// or `${#arr[@]}`.
+    Length,
+
+    /// The indices or keys of an array, e.g. `${!arr[@]}`.
+    Indices,
+
+    /// A substring of the value, or a range of elements, given by an offset
+    /// and an optional length, e.g. `${arr[@]:1:2}`.
+    Slice(String, Option<String>),
+
+    /// A word used depending on whether the parameter is set, e.g.
+    /// `${name:-default}`. If it's unset, `-` expands the word instead, `=`
+    /// also assigns it and `?` reports it as an error. If it's set, `+`
+    /// expands the word instead. With `:`, a null value counts as unset.
+    Default {
+        operator: char,
+        is_null_unset: bool,
+        word: Word,
+    },
+}
+
+/// Number of spaces a printed command's nested lists are indented by.
+const INDENT: usize = 4;
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_command(f, self, 0)
+    }
+}
+
+impl fmt::Display for Assignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(subscript) = &self.subscript {
+            write!(f, "[{subscript}]")?;
+        }
+        write!(f, "={}", self.value)
+    }
+}
+
+impl fmt::Display for ParameterExpansion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "${{")?;
+        match self.operator {
+            ParameterOperator::Length => write!(f, "#")?,
+            ParameterOperator::Indices => write!(f, "!")?,
+            _ => {}
+        }
+        write!(f, "{}", self.name)?;
+        match &self.subscript {
+            Some(Subscript::At) => write!(f, "[@]")?,
+            Some(Subscript::Star) => write!(f, "[*]")?,
+            Some(Subscript::Element(word)) => write!(f, "[{word}]")?,
+            None => {}
+        }
+        match &self.operator {
+            ParameterOperator::Slice(offset, length) => {
+                write!(f, ":{offset}")?;
+                if let Some(length) = length {
+                    write!(f, ":{length}")?;
+                }
+            }
+            ParameterOperator::Default {
+                operator,
+                is_null_unset,
+                word,
+            } => {
+                if *is_null_unset {
+                    write!(f, ":")?;
+                }
+                write!(f, "{operator}{word}")?;
+            }
+            _ => {}
+        }
+        write!(f, "}}")
+    }
+}
+
+impl fmt::Display for BuiltIn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+        for arg in self.args() {
+            write!(f, " {arg}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Redirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Redirection::StdIn { filename } => write!(f, "< {filename}"),
+            Redirection::StdOut {
+                filename,
+                is_append,
+            } => write!(f, "{} {filename}", if *is_append { ">>" } else { ">" }),
+            Redirection::StdErr {
+                filename,
+                is_append,
+            } => write!(f, "{} {filename}", if *is_append { "2>>" } else { "2>" }),
+        }
+    }
+}
+
+impl fmt::Display for ConditionalExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConditionalExpression::Word(word) => write!(f, "{word}"),
+            ConditionalExpression::Unary(operator, operand) => write!(f, "{operator} {operand}"),
+            ConditionalExpression::Binary(left, operator, right) => {
+                write!(f, "{left} {operator} {right}")
+            }
+            ConditionalExpression::Not(expression) => write!(f, "! {expression}"),
+            ConditionalExpression::And(left, right) => write!(f, "{left} && {right}"),
+            ConditionalExpression::Or(left, right) => write!(f, "{left} || {right}"),
+            ConditionalExpression::Group(expression) => write!(f, "( {expression} )"),
+        }
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_parts(f, &self.parts, false)
+    }
+}
+
+/// Writes a list with each and-or list on its own line.
+fn write_list(f: &mut fmt::Formatter<'_>, list: &List, indent: usize) -> fmt::Result {
+    for (i, and_or) in list.iter().enumerate() {
+        if i > 0 {
+            writeln!(f)?;
+        }
+        write!(f, "{:indent$}", "")?;
+        write_and_or(f, and_or, indent)?;
+    }
+    Ok(())
+}
+
+/// Writes a list on one line, e.g. the condition of an if command.
+fn write_inline_list(f: &mut fmt::Formatter<'_>, list: &List, indent: usize) -> fmt::Result {
+    for (i, and_or) in list.iter().enumerate() {
+        // A list in the background is already separated from the next one.
+        match i {
+            0 => {}
+            _ if list[i - 1].is_async => write!(f, " ")?,
+            _ => write!(f, "; ")?,
+        }
+        write_and_or(f, and_or, indent)?;
+    }
+    Ok(())
+}
+
+fn write_and_or(f: &mut fmt::Formatter<'_>, and_or: &AndOr, indent: usize) -> fmt::Result {
+    write_pipeline(f, &and_or.first, indent)?;
+    for (connector, pipeline) in &and_or.rest {
+        match connector {
+            Connector::And => write!(f, " && ")?,
+            Connector::Or => write!(f, " || ")?,
+        }
+        write_pipeline(f, pipeline, indent)?;
+    }
+    if and_or.is_async {
+        write!(f, " &")?;
+    }
+    Ok(())
+}
+
+fn write_pipeline(f: &mut fmt::Formatter<'_>, pipeline: &Pipeline, indent: usize) -> fmt::Result {
+    if pipeline.is_negated {
+        write!(f, "! ")?;
+    }
+    for (i, command) in pipeline.commands.iter().enumerate() {
+        if i > 0 {
+            write!(f, " | ")?;
+        }
+        write_command(f, command, indent)?;
+    }
+    Ok(())
+}
+
+/// Writes a command whose first line has already been indented. Any further
+/// lines are indented by `indent` spaces.
+fn write_command(f: &mut fmt::Formatter<'_>, command: &Command, indent: usize) -> fmt::Result {
+    let inner = indent + INDENT;
+
+    let redirections = match command {
+        Command::BuiltIn(command) => {
+            for assignment in &command.assignments {
+                write!(f, "{assignment} ")?;
+            }
+            write!(f, "{}", command.built_in)?;
+            &command.redirections
+        }
+        Command::External(command) => {
+            let assignments = command.assignments.iter().map(Assignment::to_string);
+            let args = command.args.iter().map(Word::to_string);
+            let words: Vec<String> = assignments.chain(args).collect();
+            write!(f, "{}", words.join(" "))?;
+            &command.redirections
+        }
+        Command::Arithmetic(command) => {
+            write!(f, "(({}))", command.expression)?;
+            &command.redirections
+        }
+        Command::ArithmeticFor(command) => {
+            write!(
+                f,
+                "for (({};{};{}))",
+                command.init, command.condition, command.update
+            )?;
+            write_do_group(f, &command.body, indent)?;
+            &command.redirections
+        }
+        Command::Case(command) => {
+            writeln!(f, "case {} in", command.word)?;
+            for item in &command.items {
+                write!(f, "{:inner$}", "")?;
+                for (i, pattern) in item.patterns.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{pattern}")?;
+                }
+                writeln!(f, ")")?;
+                if !item.body.is_empty() {
+                    write_list(f, &item.body, inner + INDENT)?;
+                    writeln!(f)?;
+                }
+                let terminator = match item.terminator {
+                    CaseTerminator::Break => ";;",
+                    CaseTerminator::FallThrough => ";&",
+                    CaseTerminator::Continue => ";;&",
+                };
+                writeln!(f, "{:inner$}{terminator}", "")?;
+            }
+            write!(f, "{:indent$}esac", "")?;
+            &command.redirections
+        }
+        Command::Conditional(command) => {
+            write!(f, "[[ {} ]]", command.expression)?;
+            &command.redirections
+        }
+        Command::For(command) => {
+            write!(f, "for {}", command.name)?;
+            if let Some(words) = &command.words {
+                write!(f, " in")?;
+                for word in words {
+                    write!(f, " {word}")?;
+                }
+            }
+            write_do_group(f, &command.body, indent)?;
+            &command.redirections
+        }
+        Command::FunctionDefinition(definition) => {
+            writeln!(f, "{} ()", definition.name)?;
+            write!(f, "{:indent$}", "")?;
+            return write_command(f, &definition.body, indent);
+        }
+        Command::Group(command) => {
+            writeln!(f, "{{")?;
+            write_list(f, &command.body, inner)?;
+            write!(f, "\n{:indent$}}}", "")?;
+            &command.redirections
+        }
+        Command::If(command) => {
+            for (i, (condition, body)) in command.branches.iter().enumerate() {
+                if i == 0 {
+                    write!(f, "if ")?;
+                } else {
+                    write!(f, "{:indent$}elif ", "")?;
+                }
+                write_inline_list(f, condition, indent)?;
+                writeln!(f, "; then")?;
+                write_list(f, body, inner)?;
+                writeln!(f)?;
+            }
+            if let Some(body) = &command.else_body {
+                writeln!(f, "{:indent$}else", "")?;
+                write_list(f, body, inner)?;
+                writeln!(f)?;
+            }
+            write!(f, "{:indent$}fi", "")?;
+            &command.redirections
+        }
+        Command::Subshell(command) => {
+            writeln!(f, "(")?;
+            write_list(f, &command.body, inner)?;
+            write!(f, "\n{:indent$})", "")?;
+            &command.redirections
+        }
+        Command::While(command) => {
+            write!(f, "{} ", if command.is_until { "until" } else { "while" })?;
+            write_inline_list(f, &command.condition, indent)?;
+            write_do_group(f, &command.body, indent)?;
+            &command.redirections
+        }
+    };
+
+    write_redirections(f, redirections)
+}
+
+/// Writes a loop body from the `;` before its `do`.
+fn write_do_group(f: &mut fmt::Formatter<'_>, body: &List, indent: usize) -> fmt::Result {
+    writeln!(f, "; do")?;
+    write_list(f, body, indent + INDENT)?;
+    write!(f, "\n{:indent$}done", "")
+}
+
+fn write_redirections(f: &mut fmt::Formatter<'_>, redirections: &[Redirection]) -> fmt::Result {
+    for redirection in redirections {
+        write!(f, " {redirection}")?;
+    }
+    Ok(())
+}
+
+/// Writes word parts as source text which parses back into the same parts.
+fn write_parts(f: &mut fmt::Formatter<'_>, parts: &[WordPart], is_quoted: bool) -> fmt::Result {
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            WordPart::Literal(s) if is_quoted => {
+                for c in s.chars() {
+                    if matches!(c, '"' | '\\' | '$') {
+                        write!(f, "\\")?;
+                    }
+                    write!(f, "{c}")?;
+                }
+            }
+            WordPart::Literal(s) => write!(f, "{s}")?,
+            WordPart::Quoted(s) if s.chars().count() == 1 => write!(f, "\\{s}")?,
+            WordPart::Quoted(s) => write!(f, "'{}'", s.replace('\'', "'\\''"))?,
+            WordPart::DoubleQuoted(parts) => {
+                write!(f, "\"")?;
+                write_parts(f, parts, true)?;
+                write!(f, "\"")?;
+            }
+            WordPart::Parameter(name) => {
+                // Braces keep the name from running into following text.
+                let is_followed_by_name_char = matches!(
+                    parts.get(i + 1),
+                    Some(WordPart::Literal(s))
+                        if s.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+                );
+                let is_multi_digit =
+                    name.len() > 1 && name.starts_with(|c: char| c.is_ascii_digit());
+                if is_followed_by_name_char || is_multi_digit {
+                    write!(f, "${{{name}}}")?;
+                } else {
+                    write!(f, "${name}")?;
+                }
+            }
+            WordPart::Arithmetic(expression) => write!(f, "$(({expression}))")?,
+            WordPart::Command(command_text) => write!(f, "$({command_text})")?,
+            WordPart::Braced(expansion) => write!(f, "{expansion}")?,
+            WordPart::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(Word::to_string).collect();
+                write!(f, "({})", elements.join(" "))?;
+            }
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,363 @@
+//! Conditions evaluated by the `test` and `[` builtins, e.g. `[ -f file ]`,
+//! and the tests they share with conditional commands, e.g. `[[ -f file ]]`.
+//!
+//! Like other shells, the meaning of the arguments depends on how many
+//! there are, as POSIX describes, so e.g. `[ -n ]` tests if `-n` is empty
+//! rather than being a missing operand. With more than four arguments, they
+//! are parsed as an expression, in which `!` binds tightest, then `-a`, then
+//! `-o`.
+
+use std::ffi::CString;
+use std::fs::{self, Metadata};
+use std::io::Write;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+
+use anyhow::anyhow;
+
+use crate::error::EvalError;
+
+/// Evaluates the `test` builtin, or `[` if the arguments must end with `]`.
+/// Returns 0 if the condition is true, 1 if it's false or 2 for an error.
+pub fn test<TErr: Write>(name: &str, args: &[String], stderr: &mut TErr) -> anyhow::Result<i32> {
+    let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if name == "[" {
+        if args.last() != Some(&"]") {
+            writeln!(stderr, "[: missing `]'")?;
+            return Ok(2);
+        }
+        args.pop();
+    }
+
+    match evaluate(&args) {
+        Ok(true) => Ok(0),
+        Ok(false) => Ok(1),
+        Err(e) => {
+            writeln!(stderr, "{name}: {e}")?;
+            Ok(2)
+        }
+    }
+}
+
+/// Determines if an argument is an operator which tests one operand, e.g.
+/// `-f`.
+pub fn is_unary_operator(arg: &str) -> bool {
+    matches!(
+        arg,
+        "-b" | "-c"
+            | "-d"
+            | "-e"
+            | "-f"
+            | "-g"
+            | "-h"
+            | "-k"
+            | "-L"
+            | "-n"
+            | "-p"
+            | "-r"
+            | "-s"
+            | "-S"
+            | "-t"
+            | "-u"
+            | "-w"
+            | "-x"
+            | "-z"
+    )
+}
+
+/// Determines if an argument is an operator which compares two operands,
+/// e.g. `-eq`.
+pub fn is_binary_operator(arg: &str) -> bool {
+    matches!(
+        arg,
+        "=" | "=="
+            | "!="
+            | "<"
+            | ">"
+            | "-eq"
+            | "-ne"
+            | "-lt"
+            | "-le"
+            | "-gt"
+            | "-ge"
+            | "-nt"
+            | "-ot"
+            | "-ef"
+    )
+}
+
+/// Evaluates a test of one operand, e.g. if a file exists.
+pub fn unary_test(operator: &str, operand: &str) -> anyhow::Result<bool> {
+    let path = Path::new(operand);
+    let metadata = fs::metadata(path).ok();
+    let has_mode = |bits: u32| {
+        metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.permissions().mode() & bits != 0)
+    };
+    let has_type = |is_type: fn(&Metadata) -> bool| metadata.as_ref().is_some_and(is_type);
+
+    let is_true = match operator {
+        "-b" => has_type(|metadata| metadata.file_type().is_block_device()),
+        "-c" => has_type(|metadata| metadata.file_type().is_char_device()),
+        "-d" => has_type(Metadata::is_dir),
+        "-e" => metadata.is_some(),
+        "-f" => has_type(Metadata::is_file),
+        "-g" => has_mode(0o2000),
+        "-h" | "-L" => fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_symlink()),
+        "-k" => has_mode(0o1000),
+        "-n" => !operand.is_empty(),
+        "-p" => has_type(|metadata| metadata.file_type().is_fifo()),
+        "-r" => is_accessible(path, libc::R_OK),
+        "-s" => has_type(|metadata| metadata.len() > 0),
+        "-S" => has_type(|metadata| metadata.file_type().is_socket()),
+        "-t" => unsafe { libc::isatty(parse_integer(operand)? as libc::c_int) == 1 },
+        "-u" => has_mode(0o4000),
+        "-w" => is_accessible(path, libc::W_OK),
+        "-x" => is_accessible(path, libc::X_OK),
+        "-z" => operand.is_empty(),
+        _ => Err(anyhow!("{operator}: unary operator expected"))?,
+    };
+    Ok(is_true)
+}
+
+/// Evaluates a comparison of two operands, e.g. if two integers are equal.
+pub fn binary_test(left: &str, operator: &str, right: &str) -> anyhow::Result<bool> {
+    let modified = |path: &str| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    let compare_integers = || -> anyhow::Result<std::cmp::Ordering> {
+        Ok(parse_integer(left)?.cmp(&parse_integer(right)?))
+    };
+
+    let is_true = match operator {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "-eq" => compare_integers()?.is_eq(),
+        "-ne" => compare_integers()?.is_ne(),
+        "-lt" => compare_integers()?.is_lt(),
+        "-le" => compare_integers()?.is_le(),
+        "-gt" => compare_integers()?.is_gt(),
+        "-ge" => compare_integers()?.is_ge(),
+        "-nt" => match (modified(left), modified(right)) {
+            (Some(left), Some(right)) => left > right,
+            (left, right) => left.is_some() && right.is_none(),
+        },
+        "-ot" => match (modified(left), modified(right)) {
+            (Some(left), Some(right)) => left < right,
+            (left, right) => left.is_none() && right.is_some(),
+        },
+        "-ef" => match (fs::metadata(left), fs::metadata(right)) {
+            (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+            _ => false,
+        },
+        _ => Err(anyhow!("{operator}: binary operator expected"))?,
+    };
+    Ok(is_true)
+}
+
+/// Matches a text against a POSIX extended regular expression, e.g. for `=~`.
+/// Returns the parts of the text matched by the expression and by each of its
+/// parenthesized subexpressions, or `None` if it doesn't match.
+pub fn regex_match(regex: &str, text: &str) -> anyhow::Result<Option<Vec<String>>> {
+    let invalid = || EvalError::with_status(format!("{regex}: invalid regular expression"), 2);
+    let regex_c = CString::new(regex).map_err(|_| invalid())?;
+    let Ok(text_c) = CString::new(text) else {
+        return Ok(None);
+    };
+
+    // SAFETY: the compiled expression is only used between `regcomp`
+    // succeeding and `regfree`, and `regexec` is given as many matches as
+    // there are in the vector.
+    let matches = unsafe {
+        let mut compiled: libc::regex_t = mem::zeroed();
+        if libc::regcomp(&mut compiled, regex_c.as_ptr(), libc::REG_EXTENDED) != 0 {
+            Err(invalid())?;
+        }
+        let empty = libc::regmatch_t {
+            rm_so: -1,
+            rm_eo: -1,
+        };
+        let mut matches = vec![empty; count_subexpressions(regex) + 1];
+        let status = libc::regexec(
+            &compiled,
+            text_c.as_ptr(),
+            matches.len(),
+            matches.as_mut_ptr(),
+            0,
+        );
+        libc::regfree(&mut compiled);
+        if status != 0 {
+            return Ok(None);
+        }
+        matches
+    };
+
+    // Subexpressions which didn't take part in the match are empty.
+    let groups = matches
+        .iter()
+        .map(|m| {
+            let range = usize::try_from(m.rm_so).ok()?..usize::try_from(m.rm_eo).ok()?;
+            text.get(range)
+        })
+        .map(|group| group.unwrap_or_default().to_string())
+        .collect();
+    Ok(Some(groups))
+}
+
+/// Evaluates the arguments of `test`, choosing their meaning by how many
+/// there are.
+fn evaluate(args: &[&str]) -> anyhow::Result<bool> {
+    match *args {
+        [] => Ok(false),
+        [arg] => Ok(!arg.is_empty()),
+        ["!", arg] => Ok(arg.is_empty()),
+        [operator, operand] if is_unary_operator(operator) => unary_test(operator, operand),
+        [arg, _] => Err(anyhow!("{arg}: unary operator expected")),
+        [left, operator, right] if is_binary_operator(operator) => {
+            binary_test(left, operator, right)
+        }
+        [left, "-a", right] => Ok(!left.is_empty() && !right.is_empty()),
+        [left, "-o", right] => Ok(!left.is_empty() || !right.is_empty()),
+        ["!", ..] if args.len() <= 4 => Ok(!evaluate(&args[1..])?),
+        ["(", arg, ")"] => Ok(!arg.is_empty()),
+        ["(", first, second, ")"] => evaluate(&[first, second]),
+        _ => {
+            let mut parser = Parser { args, position: 0 };
+            let is_true = parser.or()?;
+            match parser.args.get(parser.position) {
+                Some(_) => Err(anyhow!("too many arguments")),
+                None => Ok(is_true),
+            }
+        }
+    }
+}
+
+/// Parses and evaluates the arguments of `test` as an expression.
+struct Parser<'a> {
+    args: &'a [&'a str],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Evaluates expressions joined by `-o`.
+    fn or(&mut self) -> anyhow::Result<bool> {
+        let mut is_true = self.and()?;
+        while self.next_if("-o") {
+            let right = self.and()?;
+            is_true = is_true || right;
+        }
+        Ok(is_true)
+    }
+
+    /// Evaluates expressions joined by `-a`.
+    fn and(&mut self) -> anyhow::Result<bool> {
+        let mut is_true = self.not()?;
+        while self.next_if("-a") {
+            let right = self.not()?;
+            is_true = is_true && right;
+        }
+        Ok(is_true)
+    }
+
+    /// Evaluates an expression which may be negated by `!`.
+    fn not(&mut self) -> anyhow::Result<bool> {
+        if self.next_if("!") {
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    /// Evaluates a test, a string or an expression in parentheses.
+    fn primary(&mut self) -> anyhow::Result<bool> {
+        let args = &self.args[self.position..];
+        let (is_true, length) = match args {
+            [] => Err(anyhow!("argument expected"))?,
+            [left, operator, right, ..] if is_binary_operator(operator) => {
+                (binary_test(left, operator, right)?, 3)
+            }
+            ["(", ..] => {
+                self.position += 1;
+                let is_true = self.or()?;
+                if !self.next_if(")") {
+                    Err(anyhow!("`)' expected"))?;
+                }
+                return Ok(is_true);
+            }
+            [operator, operand, ..] if is_unary_operator(operator) => {
+                (unary_test(operator, operand)?, 2)
+            }
+            [arg, ..] => (!arg.is_empty(), 1),
+        };
+        self.position += length;
+        Ok(is_true)
+    }
+
+    /// Moves past the next argument if it's the given one. Returns true if
+    /// it was.
+    fn next_if(&mut self, arg: &str) -> bool {
+        let is_next = self.args.get(self.position) == Some(&arg);
+        if is_next {
+            self.position += 1;
+        }
+        is_next
+    }
+}
+
+/// Parses an integer operand, which may have blanks around it.
+fn parse_integer(text: &str) -> anyhow::Result<i64> {
+    text.trim()
+        .parse()
+        .map_err(|_| anyhow!("{text}: integer expression expected"))
+}
+
+/// Counts the parenthesized subexpressions of a regular expression, which
+/// `regex_t` doesn't expose.
+fn count_subexpressions(regex: &str) -> usize {
+    let mut count = 0;
+    let mut chars = regex.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '(' => count += 1,
+            // Parentheses in a bracket expression, e.g. `[()]`, match
+            // themselves. A `]` right after the `[` or `[^` is in the set.
+            '[' => {
+                chars.next_if_eq(&'^');
+                chars.next_if_eq(&']');
+                while let Some(c) = chars.next() {
+                    match c {
+                        ']' => break,
+                        '[' if matches!(chars.peek(), Some(':' | '.' | '=')) => {
+                            let delimiter = chars.next();
+                            while let Some(c) = chars.next() {
+                                if Some(c) == delimiter && chars.next_if_eq(&']').is_some() {
+                                    break;
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    count
+}
+
+/// Determines if the shell can access a file in a way, e.g. read it.
+fn is_accessible(path: &Path, mode: libc::c_int) -> bool {
+    match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), mode) == 0 },
+        Err(_) => false,
+    }
+}
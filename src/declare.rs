@@ -0,0 +1,391 @@
+//! Built-ins which declare variables and set their attributes: `declare`,
+//! `typeset`, `local`, `export`, `readonly` and `unset`.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::anyhow;
+
+use crate::expand::DeclarationArgument;
+use crate::scanner::is_name;
+use crate::shell::Shell;
+use crate::variable::{Value, Variable};
+
+/// Options given to a declaration built-in, e.g. `-x` or `+x`.
+#[derive(Default)]
+struct Options {
+    /// Attribute letters to turn on.
+    on: Vec<char>,
+
+    /// Attribute letters to turn off.
+    off: Vec<char>,
+
+    /// `-p` was given, so variables are printed.
+    is_print: bool,
+
+    /// `-g` was given, so variables in functions aren't made local.
+    is_global: bool,
+}
+
+/// Evaluates `declare` or `typeset`, which set attributes from `-aAilrux`
+/// and make variables local when called in a function.
+pub fn declare<TOut: Write, TErr: Write>(
+    shell: &mut Shell,
+    name: &str,
+    args: &[DeclarationArgument],
+    stdout: &mut TOut,
+    stderr: &mut TErr,
+) -> anyhow::Result<i32> {
+    let Some((options, count)) = parse_options(name, texts(args), "aAgilprux", stderr)? else {
+        return Ok(2);
+    };
+    let operands = &args[count..];
+
+    if options.is_print || operands.is_empty() {
+        return print_variables(shell, name, &options.on, operands, stdout, stderr);
+    }
+
+    let is_local = shell.is_in_function() && !options.is_global;
+    declare_operands(shell, name, &options, operands, is_local, stderr)
+}
+
+/// Evaluates `local`, which takes the same attributes as `declare`.
+pub fn local<TErr: Write>(
+    shell: &mut Shell,
+    args: &[DeclarationArgument],
+    stderr: &mut TErr,
+) -> anyhow::Result<i32> {
+    let Some((options, count)) = parse_options("local", texts(args), "aAilrux", stderr)? else {
+        return Ok(2);
+    };
+    let operands = &args[count..];
+    if !shell.is_in_function() {
+        return Err(anyhow!("local: can only be used in a function"));
+    }
+    declare_operands(shell, "local", &options, operands, true, stderr)
+}
+
+/// Evaluates `export`, which marks variables to be passed to child
+/// processes, or with `-n` stops passing them.
+pub fn export<TOut: Write, TErr: Write>(
+    shell: &mut Shell,
+    args: &[DeclarationArgument],
+    stdout: &mut TOut,
+    stderr: &mut TErr,
+) -> anyhow::Result<i32> {
+    let Some((mut options, count)) = parse_options("export", texts(args), "np", stderr)? else {
+        return Ok(2);
+    };
+    let operands = &args[count..];
+
+    if options.is_print || operands.is_empty() {
+        return print_variables(shell, "export", &['x'], &[], stdout, stderr);
+    }
+
+    let is_unexport = options.on.contains(&'n');
+    options.on.clear();
+    if is_unexport {
+        options.off.push('x');
+    } else {
+        options.on.push('x');
+    }
+    declare_operands(shell, "export", &options, operands, false, stderr)
+}
+
+/// Evaluates `readonly`, which stops variables from being changed.
+pub fn readonly<TOut: Write, TErr: Write>(
+    shell: &mut Shell,
+    args: &[DeclarationArgument],
+    stdout: &mut TOut,
+    stderr: &mut TErr,
+) -> anyhow::Result<i32> {
+    let Some((mut options, count)) = parse_options("readonly", texts(args), "aAp", stderr)? else {
+        return Ok(2);
+    };
+    let operands = &args[count..];
+
+    if options.is_print || operands.is_empty() {
+        return print_variables(shell, "readonly", &['r'], &[], stdout, stderr);
+    }
+
+    options.on.push('r');
+    declare_operands(shell, "readonly", &options, operands, false, stderr)
+}
+
+/// Evaluates `unset`, which removes variables or array elements, or
+/// functions with `-f`. Without options, a name with no variable removes a
+/// function.
+pub fn unset<TErr: Write>(
+    shell: &mut Shell,
+    args: &[String],
+    stderr: &mut TErr,
+) -> anyhow::Result<i32> {
+    let texts = args.iter().map(|arg| Some(arg.as_str()));
+    let Some((options, count)) = parse_options("unset", texts, "fv", stderr)? else {
+        return Ok(2);
+    };
+    let is_function = options.on.contains(&'f');
+    let is_variable = options.on.contains(&'v');
+
+    let mut status = 0;
+    for operand in &args[count..] {
+        if is_function {
+            shell.functions.remove(operand);
+            continue;
+        }
+        let (name, key) = split_element(operand);
+        if !is_variable && key.is_none() && !shell.has_variable(name) {
+            shell.functions.remove(name);
+            continue;
+        }
+        let result = match key {
+            Some(key) => shell.unset_element(name, key),
+            None => shell.unset_variable(name),
+        };
+        if let Err(e) = result {
+            writeln!(stderr, "unset: {e}")?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+/// Gets the text of each argument, or `None` for array assignments, which
+/// can't be options.
+fn texts(args: &[DeclarationArgument]) -> impl Iterator<Item = Option<&str>> {
+    args.iter().map(DeclarationArgument::text)
+}
+
+/// Splits an array element's name, e.g. `arr[1]`, into the array's name and
+/// the element's subscript.
+fn split_element(name: &str) -> (&str, Option<&str>) {
+    match name.strip_suffix(']').and_then(|name| name.split_once('[')) {
+        Some((name, key)) => (name, Some(key)),
+        None => (name, None),
+    }
+}
+
+/// Parses the options before a built-in's operands. Options end at the
+/// first argument which doesn't start with `-` or `+`, or after `--`.
+/// Returns the options and the number of arguments they took, or `None`,
+/// having reported the error, if an option isn't one of `allowed`.
+fn parse_options<'a, TErr: Write>(
+    name: &str,
+    args: impl Iterator<Item = Option<&'a str>>,
+    allowed: &str,
+    stderr: &mut TErr,
+) -> anyhow::Result<Option<(Options, usize)>> {
+    let mut options = Options::default();
+
+    let mut i = 0;
+    for arg in args {
+        let Some(arg) = arg else {
+            break;
+        };
+        if arg == "--" {
+            i += 1;
+            break;
+        }
+        let is_on = arg.starts_with('-');
+        if !(is_on || arg.starts_with('+')) || arg.len() == 1 {
+            break;
+        }
+
+        for c in arg.chars().skip(1) {
+            if !allowed.contains(c) {
+                writeln!(stderr, "{name}: {}{c}: invalid option", &arg[..1])?;
+                return Ok(None);
+            }
+            match c {
+                'p' => options.is_print = true,
+                'g' => options.is_global = true,
+                c if is_on => options.on.push(c),
+                c => options.off.push(c),
+            }
+        }
+        i += 1;
+    }
+
+    Ok(Some((options, i)))
+}
+
+/// Declares each operand, which is a name with an optional `=value`. Returns
+/// a failing status if any couldn't be declared.
+fn declare_operands<TErr: Write>(
+    shell: &mut Shell,
+    name: &str,
+    options: &Options,
+    operands: &[DeclarationArgument],
+    is_local: bool,
+    stderr: &mut TErr,
+) -> anyhow::Result<i32> {
+    let mut status = 0;
+    for operand in operands {
+        if let Err(e) = declare_operand(shell, options, operand, is_local) {
+            writeln!(stderr, "{name}: {e}")?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+fn declare_operand(
+    shell: &mut Shell,
+    options: &Options,
+    operand: &DeclarationArgument,
+    is_local: bool,
+) -> anyhow::Result<()> {
+    let (name, value) = match operand {
+        DeclarationArgument::Text(text) => match text.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (text.as_str(), None),
+        },
+        DeclarationArgument::Array(name, _) => (name.as_str(), None),
+    };
+    let (name, key) = split_element(name);
+    if !is_name(name) {
+        let operand = operand.text().unwrap_or(name);
+        return Err(anyhow!("`{operand}': not a valid identifier"));
+    }
+    let is_assigned = value.is_some() || matches!(operand, DeclarationArgument::Array(..));
+
+    if is_local {
+        shell.make_local(name)?;
+    }
+
+    let variable = shell.declare_variable(name);
+    let attributes = &mut variable.attributes;
+    if attributes.is_readonly && (is_assigned || !options.off.is_empty()) {
+        return Err(anyhow!("{name}: readonly variable"));
+    }
+
+    for &c in &options.off {
+        match c {
+            'x' => attributes.is_exported = false,
+            'i' => attributes.is_integer = false,
+            'l' => attributes.is_lowercase = false,
+            'u' => attributes.is_uppercase = false,
+            'a' | 'A' => {
+                return Err(anyhow!(
+                    "{name}: cannot destroy array variables in this way"
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    for &c in &options.on {
+        match c {
+            'x' => attributes.is_exported = true,
+            'i' => attributes.is_integer = true,
+            'l' => {
+                attributes.is_lowercase = true;
+                attributes.is_uppercase = false;
+            }
+            'u' => {
+                attributes.is_uppercase = true;
+                attributes.is_lowercase = false;
+            }
+            _ => {}
+        }
+    }
+
+    if options.on.contains(&'a') {
+        variable.value = match variable.value.take() {
+            None => Some(Value::Indexed(BTreeMap::new())),
+            Some(Value::Scalar(value)) => Some(Value::Indexed(BTreeMap::from([(0, value)]))),
+            Some(Value::Associative(items)) => {
+                variable.value = Some(Value::Associative(items));
+                return Err(anyhow!(
+                    "{name}: cannot convert associative to indexed array"
+                ));
+            }
+            indexed => indexed,
+        };
+    }
+
+    if options.on.contains(&'A') {
+        variable.value = match variable.value.take() {
+            None => Some(Value::Associative(BTreeMap::new())),
+            Some(Value::Scalar(value)) => Some(Value::Associative(BTreeMap::from([(
+                String::from("0"),
+                value,
+            )]))),
+            Some(Value::Indexed(items)) => {
+                variable.value = Some(Value::Indexed(items));
+                return Err(anyhow!(
+                    "{name}: cannot convert indexed to associative array"
+                ));
+            }
+            associative => associative,
+        };
+    }
+
+    match (operand, key, value) {
+        (DeclarationArgument::Array(_, elements), ..) => {
+            shell.set_array(name, elements.clone())?;
+        }
+        (_, Some(key), Some(value)) => shell.set_element(name, key, value.to_string())?,
+        (_, None, Some(value)) => shell.set_variable(name, value.to_string())?,
+        _ => {}
+    }
+
+    // Readonly is set last so the value above can still be assigned.
+    if options.on.contains(&'r') {
+        shell.declare_variable(name).attributes.is_readonly = true;
+    }
+
+    Ok(())
+}
+
+/// Prints variables as `declare` commands. With names, prints those
+/// variables. Otherwise prints all variables which have every attribute in
+/// `attributes`.
+fn print_variables<TOut: Write, TErr: Write>(
+    shell: &Shell,
+    name: &str,
+    attributes: &[char],
+    names: &[DeclarationArgument],
+    stdout: &mut TOut,
+    stderr: &mut TErr,
+) -> anyhow::Result<i32> {
+    if !names.is_empty() {
+        let mut status = 0;
+        for variable_name in names.iter().filter_map(DeclarationArgument::text) {
+            // Dynamic variables, e.g. `RANDOM`, only have a value.
+            let variable = match shell.variable(variable_name) {
+                Some(variable) => Some(variable.clone()),
+                None if shell.has_variable(variable_name) => Some(Variable {
+                    value: shell.get_variable(variable_name).map(Value::Scalar),
+                    ..Variable::default()
+                }),
+                None => None,
+            };
+            match variable {
+                Some(variable) => writeln!(stdout, "{}", variable.declaration(variable_name))?,
+                None => {
+                    writeln!(stderr, "{name}: {variable_name}: not found")?;
+                    status = 1;
+                }
+            }
+        }
+        return Ok(status);
+    }
+
+    for (variable_name, variable) in shell.variables() {
+        let has_attributes = attributes.iter().all(|&c| match c {
+            'x' => variable.attributes.is_exported,
+            'r' => variable.attributes.is_readonly,
+            'i' => variable.attributes.is_integer,
+            'l' => variable.attributes.is_lowercase,
+            'u' => variable.attributes.is_uppercase,
+            'a' => matches!(variable.value, Some(Value::Indexed(_))),
+            'A' => matches!(variable.value, Some(Value::Associative(_))),
+            _ => true,
+        });
+        if has_attributes {
+            writeln!(stdout, "{}", variable.declaration(variable_name))?;
+        }
+    }
+    Ok(0)
+}
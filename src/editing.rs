@@ -1,5 +1,6 @@
 //! Module used to handle rustyline library.
 
+use crate::ast::BUILT_INS;
 use crate::system::*;
 use rustyline::completion::Candidate;
 use rustyline::history::FileHistory;
@@ -11,8 +12,9 @@ use std::path::PathBuf;
 pub fn create_editor(paths: &[PathBuf]) -> anyhow::Result<Editor<ShellHelper<'_>, FileHistory>> {
     let completer = ShellCompleter::new(paths);
     let helper = ShellHelper::new(completer);
+    // Commands are added to the history once they're complete, as one entry
+    // however many lines they have.
     let config = Config::builder()
-        .auto_add_history(true)
         .completion_type(CompletionType::List)
         .build();
     let mut editor = Editor::with_config(config)?;
@@ -20,6 +22,9 @@ pub fn create_editor(paths: &[PathBuf]) -> anyhow::Result<Editor<ShellHelper<'_>
     Ok(editor)
 }
 
+/// The derived `Validator` accepts every line. A rustyline validator can't
+/// show a prompt on continuation lines, so `run_interactive` reads the rest
+/// of an incomplete command itself, with the `PS2` prompt.
 #[derive(Helper, Completer, Hinter, Highlighter, Validator)]
 pub struct ShellHelper<'a> {
     #[rustyline(Completer)]
@@ -30,15 +35,26 @@ impl<'a> ShellHelper<'a> {
     fn new(completer: ShellCompleter<'a>) -> Self {
         Self { completer }
     }
+
+    /// Sets the names of aliases to complete.
+    pub fn set_aliases(&mut self, aliases: Vec<String>) {
+        self.completer.aliases = aliases;
+    }
 }
 
 pub struct ShellCompleter<'a> {
     paths: &'a [PathBuf],
+
+    /// Names of the shell's aliases.
+    aliases: Vec<String>,
 }
 
 impl<'a> ShellCompleter<'a> {
     fn new(paths: &'a [PathBuf]) -> Self {
-        Self { paths }
+        Self {
+            paths,
+            aliases: Vec::new(),
+        }
     }
 }
 
@@ -55,11 +71,13 @@ impl<'a> rustyline::completion::Completer for ShellCompleter<'a> {
             let mut trie_builder = trie_builder_with_path_executables(self.paths);
 
             // Add built-in commands to trie builder.
-            trie_builder.push("cd");
-            trie_builder.push("echo");
-            trie_builder.push("exit");
-            trie_builder.push("pwd");
-            trie_builder.push("type");
+            for name in BUILT_INS {
+                trie_builder.push(name);
+            }
+
+            for alias in &self.aliases {
+                trie_builder.push(alias);
+            }
 
             trie_builder.build()
         };
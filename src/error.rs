@@ -6,11 +6,18 @@ use std::{error::Error, fmt};
 #[derive(Debug)]
 pub struct EvalError {
     message: String,
+    status: i32,
 }
 
 impl EvalError {
-    pub fn new(message: String) -> EvalError {
-        EvalError { message }
+    /// Creates an error which gives a command a specific exit status.
+    pub fn with_status(message: String, status: i32) -> EvalError {
+        EvalError { message, status }
+    }
+
+    /// Exit status of a command which failed with this error.
+    pub fn status(&self) -> i32 {
+        self.status
     }
 }
 
@@ -21,3 +28,132 @@ impl fmt::Display for EvalError {
 }
 
 impl Error for EvalError {}
+
+/// An error for command text which ends before its commands are complete, so
+/// more input could complete them, e.g. an unclosed quote.
+#[derive(Debug)]
+pub struct IncompleteError {
+    message: String,
+
+    /// Text which a further line needs for the commands to be complete, e.g.
+    /// `fi` for an `if` command, or an empty string if any line could
+    /// complete them, e.g. after a trailing `|`. `None` if the text ended
+    /// outside any open construct, which makes it a syntax error.
+    closer: Option<&'static str>,
+}
+
+impl IncompleteError {
+    /// Creates an error for text which ends inside a construct, e.g. a quote,
+    /// that a line with `closer` could complete.
+    pub fn new(message: &str, closer: &'static str) -> IncompleteError {
+        IncompleteError {
+            message: message.to_string(),
+            closer: Some(closer),
+        }
+    }
+
+    /// Creates an error for text which ends where more is needed. It's only
+    /// incomplete if it's inside an open construct, e.g. an `if` command.
+    pub fn at_end(message: &str) -> IncompleteError {
+        IncompleteError {
+            message: message.to_string(),
+            closer: None,
+        }
+    }
+}
+
+/// Marks an error for the end of command text as being inside an open
+/// construct, which a line with `closer` could complete, unless it's already
+/// inside a construct nested in this one.
+pub fn in_construct(e: anyhow::Error, closer: &'static str) -> anyhow::Error {
+    match e.downcast::<IncompleteError>() {
+        Ok(mut e) => {
+            e.closer = e.closer.or(Some(closer));
+            e.into()
+        }
+        Err(e) => e,
+    }
+}
+
+impl fmt::Display for IncompleteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for IncompleteError {}
+
+/// An error for command text which can't be parsed, with the number of the
+/// line the parser was on when it found the error.
+#[derive(Debug)]
+pub struct SyntaxError {
+    message: String,
+    line: usize,
+}
+
+impl SyntaxError {
+    pub fn new(message: String, line: usize) -> SyntaxError {
+        SyntaxError { message, line }
+    }
+
+    /// Number of the line the error is on.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for SyntaxError {}
+
+/// Determines if an error came from command text which was incomplete.
+pub fn is_incomplete(e: &anyhow::Error) -> bool {
+    incomplete_closer(e).is_some()
+}
+
+/// Gets the text which a further line needs to complete command text, or
+/// `None` if the error didn't come from incomplete command text.
+pub fn incomplete_closer(e: &anyhow::Error) -> Option<&'static str> {
+    e.downcast_ref::<IncompleteError>()?.closer
+}
+
+/// An error which makes a shell that isn't interactive exit, e.g. for
+/// expanding an unset variable when `nounset` is on.
+#[derive(Debug)]
+pub struct FatalError {
+    message: String,
+}
+
+impl FatalError {
+    pub fn new(message: String) -> FatalError {
+        FatalError { message }
+    }
+}
+
+impl fmt::Display for FatalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for FatalError {}
+
+/// Determines if an error should make a shell that isn't interactive exit.
+pub fn is_fatal(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<FatalError>().is_some()
+}
+
+/// Creates the error for expanding a variable which isn't set when `nounset`
+/// is on.
+pub fn unbound_variable(name: &str) -> anyhow::Error {
+    FatalError::new(format!("{name}: unbound variable")).into()
+}
+
+/// Gets the exit status of a command which failed with an error.
+pub fn error_status(e: &anyhow::Error) -> i32 {
+    e.downcast_ref::<EvalError>().map_or(1, EvalError::status)
+}
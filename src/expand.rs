@@ -0,0 +1,875 @@
+//! Word expansion.
+//!
+//! Words are expanded in the order a POSIX shell uses: brace expansion, then
+//! tilde, parameter and arithmetic expansion and command substitution, then
+//! field splitting, pathname expansion and finally quote removal.
+
+use std::fmt;
+
+use anyhow::anyhow;
+
+use crate::arith;
+use crate::ast::{
+    Assignment, BuiltIn, ParameterExpansion, ParameterOperator, Subscript, Word, WordPart,
+};
+use crate::error::{unbound_variable, FatalError};
+use crate::glob;
+use crate::parser::{array_element, assignment};
+use crate::scanner::is_name;
+use crate::shell::Shell;
+
+/// The characters in `IFS` which are whitespace. Runs of them separate
+/// fields as one, and they're trimmed from the start and end of fields.
+const IFS_WHITESPACE: &str = " \t\n";
+
+/// A character of a word being expanded.
+#[derive(Clone, Copy)]
+struct Char {
+    c: char,
+
+    /// The character was quoted, so isn't special in a glob pattern.
+    is_quoted: bool,
+
+    /// The character came from an unquoted expansion, so it may separate
+    /// fields.
+    is_splittable: bool,
+}
+
+/// A word being expanded.
+#[derive(Default)]
+struct Field {
+    chars: Vec<Char>,
+
+    /// The word contained quotes, so it's kept even if it's empty.
+    has_quotes: bool,
+
+    /// Breaks between the elements of `$@` or an array, e.g. `${arr[@]}`.
+    breaks: Vec<Break>,
+}
+
+/// A break between elements which are separate fields.
+struct Break {
+    /// Index of the char which starts the next element.
+    index: usize,
+
+    /// The elements were quoted, e.g. `"$@"`, so they're fields even if
+    /// they're empty.
+    is_quoted: bool,
+}
+
+impl Field {
+    fn push_str(&mut self, s: &str, is_quoted: bool, is_splittable: bool) {
+        self.chars.extend(s.chars().map(|c| Char {
+            c,
+            is_quoted,
+            is_splittable,
+        }));
+    }
+}
+
+/// Expands words into fields, which may produce more or fewer fields than
+/// there were words.
+pub fn expand_words(shell: &mut Shell, words: &[Word]) -> anyhow::Result<Vec<String>> {
+    let ifs = shell.ifs();
+    let mut fields = Vec::new();
+    for word in words {
+        for word in brace_expand(word) {
+            let field = expand_parts(shell, &word)?;
+            for field in split_field(field, &ifs) {
+                fields.extend(expand_pathname(field, shell)?);
+            }
+        }
+    }
+    Ok(fields)
+}
+
+/// Expands a word into a single string, without brace expansion, field
+/// splitting or pathname expansion. Elements of `$@` or an array are joined
+/// with spaces.
+pub fn expand_word(shell: &mut Shell, word: &Word) -> anyhow::Result<String> {
+    let field = expand_parts(shell, word)?;
+    let mut text = String::new();
+    let mut breaks = field.breaks.iter().peekable();
+    for (i, c) in field.chars.iter().enumerate() {
+        while breaks.next_if(|b| b.index == i).is_some() {
+            text.push(' ');
+        }
+        text.push(c.c);
+    }
+    text.extend(breaks.map(|_| ' '));
+    Ok(text)
+}
+
+/// Evaluates an assignment, which sets a variable, an array element or a
+/// whole array.
+pub fn assign(shell: &mut Shell, assignment: &Assignment) -> anyhow::Result<()> {
+    let name = &assignment.name;
+    match (&assignment.subscript, &assignment.value.parts[..]) {
+        (None, [WordPart::Array(elements)]) => {
+            let elements = expand_array(shell, elements)?;
+            shell.set_array(name, elements)
+        }
+        (Some(subscript), _) => {
+            let key = expand_word(shell, subscript)?;
+            let value = expand_word(shell, &assignment.value)?;
+            shell.set_element(name, &key, value)
+        }
+        (None, _) => {
+            let value = expand_word(shell, &assignment.value)?;
+            shell.set_variable(name, value)
+        }
+    }
+}
+
+/// Expands the elements of an array assignment. Returns each element's
+/// value with its subscript, if it has one, e.g. `[1]=value`. Elements
+/// without subscripts are expanded into fields like command arguments.
+pub fn expand_array(
+    shell: &mut Shell,
+    elements: &[Word],
+) -> anyhow::Result<Vec<(Option<String>, String)>> {
+    let mut expanded = Vec::new();
+    for element in elements {
+        match array_element(element) {
+            Some((subscript, value)) => {
+                let key = expand_word(shell, &subscript)?;
+                let value = expand_word(shell, &value)?;
+                expanded.push((Some(key), value));
+            }
+            None => {
+                let fields = expand_words(shell, std::slice::from_ref(element))?;
+                expanded.extend(fields.into_iter().map(|field| (None, field)));
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+/// Expands the values of assignment words. Returns pairs of names and
+/// values.
+pub fn expand_assignments(
+    shell: &mut Shell,
+    assignments: &[Assignment],
+) -> anyhow::Result<Vec<(String, String)>> {
+    let mut variables = Vec::new();
+    for assignment in assignments {
+        let value = expand_word(shell, &assignment.value)?;
+        variables.push((assignment.name.clone(), value));
+    }
+    Ok(variables)
+}
+
+/// An argument of a declaration built-in such as `declare`, after
+/// expansion.
+pub enum DeclarationArgument {
+    /// An option, a name or a name with a value, e.g. `name=value`.
+    Text(String),
+
+    /// An array assignment, e.g. `arr=(a b c)`, with its name and its
+    /// elements' subscripts and values.
+    Array(String, Vec<(Option<String>, String)>),
+}
+
+impl DeclarationArgument {
+    /// Gets the argument's text, unless it's an array assignment.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            Self::Array(..) => None,
+        }
+    }
+
+    /// Makes a word which expands back to the argument, with quoted text so
+    /// it isn't expanded again.
+    fn to_word(&self) -> Word {
+        match self {
+            Self::Text(text) => Word::quoted(text),
+            Self::Array(name, elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|(key, value)| match key {
+                        Some(key) => Word {
+                            parts: vec![
+                                WordPart::Literal(String::from("[")),
+                                WordPart::Quoted(key.clone()),
+                                WordPart::Literal(String::from("]=")),
+                                WordPart::Quoted(value.clone()),
+                            ],
+                        },
+                        None => Word::quoted(value),
+                    })
+                    .collect();
+                Word {
+                    parts: vec![
+                        WordPart::Literal(format!("{name}=")),
+                        WordPart::Array(elements),
+                    ],
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for DeclarationArgument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => write!(f, "{text}"),
+            Self::Array(name, elements) => {
+                write!(f, "{name}=(")?;
+                for (i, (key, value)) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    if let Some(key) = key {
+                        write!(f, "[{key}]=")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Expands the arguments of a declaration built-in such as `declare`.
+/// Arguments which look like assignments are expanded as assignment values,
+/// without field splitting or pathname expansion.
+pub fn expand_declaration_words(
+    shell: &mut Shell,
+    words: &[Word],
+) -> anyhow::Result<Vec<DeclarationArgument>> {
+    let mut arguments = Vec::new();
+    for word in words {
+        match assignment(word) {
+            Some(assignment) => match &assignment.value.parts[..] {
+                [WordPart::Array(elements)] if assignment.subscript.is_none() => {
+                    let elements = expand_array(shell, elements)?;
+                    arguments.push(DeclarationArgument::Array(assignment.name, elements));
+                }
+                _ => {
+                    let mut text = assignment.name;
+                    if let Some(subscript) = &assignment.subscript {
+                        text = format!("{text}[{}]", expand_word(shell, subscript)?);
+                    }
+                    let value = expand_word(shell, &assignment.value)?;
+                    arguments.push(DeclarationArgument::Text(format!("{text}={value}")));
+                }
+            },
+            None => {
+                let fields = expand_words(shell, std::slice::from_ref(word))?;
+                arguments.extend(fields.into_iter().map(DeclarationArgument::Text));
+            }
+        }
+    }
+    Ok(arguments)
+}
+
+/// Expands a built-in's argument words the way the built-in expands them.
+/// Returns the built-in with words which expand to exactly the expanded
+/// arguments, so it can run after variables assigned for it are set without
+/// its arguments seeing them, e.g. in `X=1 echo $X`.
+pub fn expand_built_in(shell: &mut Shell, built_in: &BuiltIn) -> anyhow::Result<BuiltIn> {
+    let words: Vec<Word> = built_in.args().into_iter().cloned().collect();
+    let words = match built_in {
+        BuiltIn::Declare(_)
+        | BuiltIn::Export(_)
+        | BuiltIn::Local(_)
+        | BuiltIn::Readonly(_)
+        | BuiltIn::Typeset(_) => expand_declaration_words(shell, &words)?
+            .iter()
+            .map(DeclarationArgument::to_word)
+            .collect(),
+        _ => expand_words(shell, &words)?
+            .iter()
+            .map(|arg| Word::quoted(arg))
+            .collect(),
+    };
+    let expanded = BuiltIn::new(built_in.name(), words)?;
+    Ok(expanded.expect("a built-in's name names a built-in"))
+}
+
+/// Expands a word into a glob pattern, e.g. for a case item, without brace
+/// expansion, field splitting or pathname expansion.
+pub fn expand_pattern(shell: &mut Shell, word: &Word) -> anyhow::Result<String> {
+    let field = expand_parts(shell, word)?;
+    Ok(to_pattern(&field))
+}
+
+/// Expands a word into a POSIX extended regular expression, e.g. for `=~`,
+/// in which quoted characters only match themselves.
+pub fn expand_regex(shell: &mut Shell, word: &Word) -> anyhow::Result<String> {
+    let field = expand_parts(shell, word)?;
+    let mut regex = String::new();
+    for c in &field.chars {
+        if c.is_quoted && "\\^$.|?*+()[]{}".contains(c.c) {
+            regex.push('\\');
+        }
+        regex.push(c.c);
+    }
+    Ok(regex)
+}
+
+/// Performs tilde, parameter and arithmetic expansion and command
+/// substitution on a word.
+fn expand_parts(shell: &mut Shell, word: &Word) -> anyhow::Result<Field> {
+    let mut field = Field::default();
+    for (i, part) in word.parts.iter().enumerate() {
+        match part {
+            WordPart::Literal(s) if i == 0 && s.starts_with('~') => {
+                let (prefix, rest) = s.split_at(s.find('/').unwrap_or(s.len()));
+                match expand_tilde(shell, prefix) {
+                    Some(home) => field.push_str(&home, true, false),
+                    None => field.push_str(prefix, false, false),
+                }
+                field.push_str(rest, false, false);
+            }
+            part => expand_part(shell, part, false, &mut field)?,
+        }
+    }
+    Ok(field)
+}
+
+fn expand_part(
+    shell: &mut Shell,
+    part: &WordPart,
+    is_quoted: bool,
+    field: &mut Field,
+) -> anyhow::Result<()> {
+    match part {
+        WordPart::Literal(s) => field.push_str(s, is_quoted, false),
+        WordPart::Quoted(s) => {
+            field.has_quotes = true;
+            field.push_str(s, true, false);
+        }
+        WordPart::DoubleQuoted(parts) => {
+            // Quotes around only `$@` or `${arr[@]}` with no elements make no
+            // field.
+            let len = field.chars.len();
+            for part in parts {
+                expand_part(shell, part, true, field)?;
+            }
+            if field.chars.len() > len || parts.is_empty() || !parts.iter().all(is_all_elements) {
+                field.has_quotes = true;
+            }
+        }
+        WordPart::Parameter(name) if matches!(name.as_str(), "@" | "*") => {
+            let ifs = shell.ifs();
+            let values = shell.positional.clone();
+            push_elements(field, &values, is_quoted, name == "@", &ifs);
+        }
+        WordPart::Parameter(name) => {
+            let value = match shell.get_variable(name) {
+                Some(value) => value,
+                None if shell.options.nounset => return Err(unbound_variable(name)),
+                None => String::new(),
+            };
+            field.push_str(&value, is_quoted, !is_quoted);
+        }
+        WordPart::Arithmetic(expression) => {
+            let value = arith::evaluate(shell, expression)?;
+            field.push_str(&value.to_string(), is_quoted, !is_quoted);
+        }
+        WordPart::Command(command_text) => {
+            let output = crate::eval_command_substitution(shell, command_text)?;
+            field.push_str(&output, is_quoted, !is_quoted);
+        }
+        WordPart::Braced(expansion) => expand_braced(shell, expansion, is_quoted, field)?,
+        WordPart::Array(_) => {
+            // An array outside an assignment is just text.
+            let text = Word {
+                parts: vec![part.clone()],
+            };
+            field.push_str(&text.to_string(), is_quoted, false);
+        }
+    }
+    Ok(())
+}
+
+/// Determines if a part expands to all of an array's elements or all the
+/// positional parameters as separate fields, e.g. `${arr[@]}` or `$@`.
+fn is_all_elements(part: &WordPart) -> bool {
+    match part {
+        WordPart::Parameter(name) => name == "@",
+        WordPart::Braced(expansion) => match expansion.subscript {
+            Some(Subscript::At) => true,
+            None => {
+                expansion.name == "@" && !matches!(expansion.operator, ParameterOperator::Length)
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Expands a parameter expansion in braces, e.g. `${arr[@]:1:2}`.
+fn expand_braced(
+    shell: &mut Shell,
+    expansion: &ParameterExpansion,
+    is_quoted: bool,
+    field: &mut Field,
+) -> anyhow::Result<()> {
+    let name = &expansion.name;
+    let is_positional = matches!(name.as_str(), "@" | "*");
+    let key = match &expansion.subscript {
+        Some(Subscript::Element(subscript)) => Some(expand_word(shell, subscript)?),
+        _ => None,
+    };
+
+    // An unset parameter isn't an error if there's a default for it.
+    let is_unbound_error =
+        shell.options.nounset && !matches!(expansion.operator, ParameterOperator::Default { .. });
+    let variable = shell.variable(name);
+
+    // For all elements, whether they're separate fields when quoted, as for
+    // `@` rather than `*`.
+    let (mut values, mut all) = match (&expansion.operator, &expansion.subscript) {
+        (_, None) if is_positional => (shell.positional.clone(), Some(name == "@")),
+        (ParameterOperator::Indices, subscript) => (
+            variable.map(|variable| variable.keys()).unwrap_or_default(),
+            Some(matches!(subscript, Some(Subscript::At))),
+        ),
+        (_, Some(subscript @ (Subscript::At | Subscript::Star))) => {
+            let values = variable
+                .map(|variable| variable.values())
+                .unwrap_or_default();
+            let values = values.into_iter().map(String::from).collect();
+            (values, Some(matches!(subscript, Subscript::At)))
+        }
+        (_, Some(Subscript::Element(_))) => {
+            let key = key.as_deref().unwrap_or_default();
+            match shell.element(name, key)? {
+                Some(value) => (vec![value], None),
+                None if is_unbound_error => {
+                    return Err(unbound_variable(&format!("{name}[{key}]")));
+                }
+                None => (vec![], None),
+            }
+        }
+        (_, None) => match shell.get_variable(name) {
+            Some(value) => (vec![value], None),
+            None if is_unbound_error => return Err(unbound_variable(name)),
+            None => (vec![], None),
+        },
+    };
+
+    match &expansion.operator {
+        ParameterOperator::Length => {
+            let length = match all {
+                Some(_) => values.len(),
+                None => values.first().map_or(0, |value| value.chars().count()),
+            };
+            values = vec![length.to_string()];
+            all = None;
+        }
+        ParameterOperator::Slice(offset, length) => {
+            let mut offset = arith::evaluate(shell, offset)?;
+            let length = match length {
+                Some(length) => Some(arith::evaluate(shell, length)?),
+                None => None,
+            };
+            // Positional parameters are numbered from one.
+            if is_positional && offset > 0 {
+                offset -= 1;
+            }
+            if all.is_some() {
+                values = slice(&values, offset, length);
+            } else {
+                let chars: Vec<char> = values.first().map_or(vec![], |v| v.chars().collect());
+                values = vec![slice(&chars, offset, length).into_iter().collect()];
+            }
+        }
+        ParameterOperator::Default {
+            operator,
+            is_null_unset,
+            word,
+        } => {
+            let is_unset =
+                values.is_empty() || (*is_null_unset && values.iter().all(String::is_empty));
+            match operator {
+                '-' if is_unset => return expand_default(shell, word, is_quoted, field),
+                '+' if !is_unset => return expand_default(shell, word, is_quoted, field),
+                '+' => return Ok(()),
+                '=' if is_unset => {
+                    let value = expand_word(shell, word)?;
+                    match &key {
+                        _ if !is_name(name) => Err(anyhow!("${name}: cannot assign in this way"))?,
+                        Some(key) => shell.set_element(name, key, value.clone())?,
+                        None => shell.set_variable(name, value.clone())?,
+                    }
+                    values = vec![value];
+                    all = None;
+                }
+                '?' if is_unset => {
+                    let message = match expand_word(shell, word)? {
+                        message if message.is_empty() => String::from("parameter null or not set"),
+                        message => message,
+                    };
+                    return Err(FatalError::new(format!("{name}: {message}")).into());
+                }
+                _ => {}
+            }
+        }
+        ParameterOperator::Value | ParameterOperator::Indices => {}
+    }
+
+    match all {
+        Some(is_at) => {
+            let ifs = shell.ifs();
+            push_elements(field, &values, is_quoted, is_at, &ifs);
+        }
+        None => {
+            for value in &values {
+                field.push_str(value, is_quoted, !is_quoted);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Expands the word in a parameter expansion which is used instead of the
+/// parameter's value, e.g. `${name:-default}`, into a field. It's quoted if
+/// the expansion is, and otherwise its unquoted text is split into fields.
+fn expand_default(
+    shell: &mut Shell,
+    word: &Word,
+    is_quoted: bool,
+    field: &mut Field,
+) -> anyhow::Result<()> {
+    for part in &word.parts {
+        match part {
+            WordPart::Literal(s) if !is_quoted => field.push_str(s, false, true),
+            part => expand_part(shell, part, is_quoted, field)?,
+        }
+    }
+    Ok(())
+}
+
+/// Adds the elements of `$@`, `$*` or an array to a field. Each element is a
+/// separate field, unless they're quoted and not from `@`, e.g. `"$*"`,
+/// when they're joined by the first character of `IFS`.
+fn push_elements(field: &mut Field, values: &[String], is_quoted: bool, is_at: bool, ifs: &str) {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            if is_quoted && !is_at {
+                if let Some(separator) = ifs.chars().next() {
+                    field.push_str(&separator.to_string(), true, false);
+                }
+            } else {
+                field.breaks.push(Break {
+                    index: field.chars.len(),
+                    is_quoted,
+                });
+            }
+        }
+        field.push_str(value, is_quoted, !is_quoted);
+    }
+}
+
+/// Takes a range of items given by an offset and an optional length. A
+/// negative offset counts back from the end, as does a negative length for
+/// the end of the range.
+fn slice<T: Clone>(items: &[T], offset: i64, length: Option<i64>) -> Vec<T> {
+    let len = items.len() as i64;
+    let start = if offset < 0 { len + offset } else { offset }.clamp(0, len);
+    let end = match length {
+        Some(length) if length < 0 => len + length,
+        Some(length) => start + length,
+        None => len,
+    }
+    .clamp(start, len);
+    items[start as usize..end as usize].to_vec()
+}
+
+/// Expands a tilde prefix to a home directory.
+fn expand_tilde(shell: &Shell, prefix: &str) -> Option<String> {
+    match prefix {
+        "~" => shell.get_variable("HOME"),
+        "~+" => shell.get_variable("PWD"),
+        "~-" => shell.get_variable("OLDPWD"),
+        _ => None,
+    }
+}
+
+/// Splits a field on `IFS` characters which came from unquoted expansions,
+/// and between elements of `$@` or an array.
+///
+/// Runs of `IFS` whitespace separate fields and are trimmed from the start
+/// and end. Each other `IFS` character separates fields along with any
+/// whitespace around it, so two in a row separate an empty field.
+fn split_field(field: Field, ifs: &str) -> Vec<Field> {
+    let has_quotes = field.has_quotes;
+    let mut fields = Vec::new();
+    let mut current = Field::default();
+
+    // Whether whitespace just ended a field, so a following non-whitespace
+    // separator doesn't end another.
+    let mut is_after_whitespace = false;
+
+    // Whether the current field is a quoted element, which is kept even if
+    // it's empty.
+    let mut is_element = false;
+
+    let mut breaks = field.breaks.iter().peekable();
+    let len = field.chars.len();
+    for i in 0..=len {
+        while let Some(b) = breaks.next_if(|b| b.index == i) {
+            if b.is_quoted || !current.chars.is_empty() {
+                fields.push(std::mem::take(&mut current));
+            }
+            is_after_whitespace = !b.is_quoted;
+            is_element = b.is_quoted;
+        }
+        let Some(&c) = field.chars.get(i) else {
+            break;
+        };
+
+        if !c.is_splittable || !ifs.contains(c.c) {
+            current.chars.push(c);
+            is_after_whitespace = false;
+        } else if IFS_WHITESPACE.contains(c.c) {
+            if !current.chars.is_empty() {
+                fields.push(std::mem::take(&mut current));
+                is_after_whitespace = true;
+            }
+            is_element = false;
+        } else {
+            if !(current.chars.is_empty() && is_after_whitespace) {
+                fields.push(std::mem::take(&mut current));
+            }
+            is_after_whitespace = false;
+            is_element = false;
+        }
+    }
+
+    if !current.chars.is_empty() || is_element || (fields.is_empty() && has_quotes) {
+        fields.push(current);
+    }
+
+    fields
+}
+
+/// Expands a field which is a glob pattern into the matching pathnames. If
+/// it isn't a pattern or `noglob` is on, the field's text is kept. If nothing
+/// matches, the text is kept too, unless `nullglob` or `failglob` is on.
+fn expand_pathname(field: Field, shell: &Shell) -> anyhow::Result<Vec<String>> {
+    let text: String = field.chars.iter().map(|c| c.c).collect();
+
+    let is_extglob = shell.shell_options.extglob;
+    let is_pattern = !shell.options.noglob
+        && field.chars.iter().any(|c| {
+            !c.is_quoted && (matches!(c.c, '*' | '?' | '[') || (is_extglob && c.c == '('))
+        });
+    if !is_pattern {
+        return Ok(vec![text]);
+    }
+
+    let pattern = to_pattern(&field);
+    let paths = glob::expand_pathname(&pattern, &shell.shell_options);
+    if !paths.is_empty() {
+        Ok(paths)
+    } else if shell.shell_options.failglob {
+        Err(anyhow!("no match: {text}"))
+    } else if shell.shell_options.nullglob {
+        Ok(vec![])
+    } else {
+        Ok(vec![text])
+    }
+}
+
+/// Converts a field to a glob pattern in which quoted characters only match
+/// themselves.
+fn to_pattern(field: &Field) -> String {
+    let mut pattern = String::new();
+    for c in &field.chars {
+        if c.is_quoted {
+            pattern.push_str(&glob::escape(&c.c.to_string()));
+        } else {
+            pattern.push(c.c);
+        }
+    }
+    pattern
+}
+
+/// A piece of a word considered during brace expansion.
+#[derive(Clone)]
+enum Unit {
+    /// An unquoted literal character.
+    Char(char),
+
+    /// Any other part of a word, which braces can't come from.
+    Part(WordPart),
+}
+
+/// Performs brace expansion, e.g. `a{b,c}` becomes `ab ac`.
+fn brace_expand(word: &Word) -> Vec<Word> {
+    let mut units = Vec::new();
+    for part in &word.parts {
+        match part {
+            WordPart::Literal(s) => units.extend(s.chars().map(Unit::Char)),
+            part => units.push(Unit::Part(part.clone())),
+        }
+    }
+
+    expand_units(&units).into_iter().map(to_word).collect()
+}
+
+fn expand_units(units: &[Unit]) -> Vec<Vec<Unit>> {
+    for open in 0..units.len() {
+        if !matches!(units[open], Unit::Char('{')) {
+            continue;
+        }
+
+        let Some((close, commas)) = find_brace_close(units, open) else {
+            continue;
+        };
+
+        let alternatives: Vec<Vec<Unit>> = if commas.is_empty() {
+            match sequence(&units[open + 1..close]) {
+                Some(items) => items
+                    .into_iter()
+                    .map(|item| item.chars().map(Unit::Char).collect())
+                    .collect(),
+                None => continue,
+            }
+        } else {
+            let mut bounds = vec![open];
+            bounds.extend(commas);
+            bounds.push(close);
+            bounds
+                .windows(2)
+                .map(|w| units[w[0] + 1..w[1]].to_vec())
+                .collect()
+        };
+
+        let mut words = Vec::new();
+        for alternative in alternatives {
+            let mut combined = units[..open].to_vec();
+            combined.extend(alternative);
+            combined.extend_from_slice(&units[close + 1..]);
+            words.extend(expand_units(&combined));
+        }
+        return words;
+    }
+
+    vec![units.to_vec()]
+}
+
+/// Finds the `}` matching the `{` at `open`. Returns its index and the
+/// indices of any commas directly inside the braces.
+fn find_brace_close(units: &[Unit], open: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    for (i, unit) in units.iter().enumerate().skip(open + 1) {
+        match unit {
+            Unit::Char('{') => depth += 1,
+            Unit::Char('}') if depth == 0 => return Some((i, commas)),
+            Unit::Char('}') => depth -= 1,
+            Unit::Char(',') if depth == 0 => commas.push(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Expands the inside of a sequence expression, e.g. `1..5` or `a..e..2`.
+fn sequence(units: &[Unit]) -> Option<Vec<String>> {
+    let mut text = String::new();
+    for unit in units {
+        match unit {
+            Unit::Char(c) => text.push(*c),
+            Unit::Part(_) => return None,
+        }
+    }
+
+    let items: Vec<&str> = text.split("..").collect();
+    let (start, end, step) = match items[..] {
+        [start, end] => (start, end, None),
+        [start, end, step] => (start, end, Some(step.parse::<i64>().ok()?)),
+        _ => return None,
+    };
+    let step = step.unwrap_or(1).unsigned_abs().max(1) as usize;
+
+    if let (Ok(low), Ok(high)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        // Numbers are padded with zeros if either end has leading zeros.
+        let is_padded = [start, end].iter().any(|s| {
+            s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0')
+        });
+        let width = if is_padded {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        let numbers: Vec<i64> = if low <= high {
+            (low..=high).step_by(step).collect()
+        } else {
+            (high..=low).rev().step_by(step).collect()
+        };
+        return Some(numbers.iter().map(|n| format!("{n:0width$}")).collect());
+    }
+
+    let (mut start_chars, mut end_chars) = (start.chars(), end.chars());
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        (Some(low), None, Some(high), None)
+            if low.is_ascii_alphabetic() && high.is_ascii_alphabetic() =>
+        {
+            let chars: Vec<char> = if low <= high {
+                (low..=high).step_by(step).collect()
+            } else {
+                (high..=low).rev().step_by(step).collect()
+            };
+            Some(chars.iter().map(|c| c.to_string()).collect())
+        }
+        _ => None,
+    }
+}
+
+/// Converts brace expansion units back into a word.
+fn to_word(units: Vec<Unit>) -> Word {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    for unit in units {
+        match unit {
+            Unit::Char(c) => literal.push(c),
+            Unit::Part(part) => {
+                if !literal.is_empty() {
+                    parts.push(WordPart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(part);
+            }
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(WordPart::Literal(literal));
+    }
+    Word { parts }
+}
+
+/// Quotes text with single quotes so it expands back to itself, e.g. in
+/// output which can be read back in as commands.
+pub fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Quotes a word if it's empty or has characters which are special to the
+/// shell, so it reads back as the same word.
+pub fn quote_special(word: &str) -> String {
+    let is_plain = |c: char| c.is_alphanumeric() || "%+,-./:=@_".contains(c);
+    if !word.is_empty() && word.chars().all(is_plain) {
+        word.to_string()
+    } else {
+        quote(word)
+    }
+}
+
+/// Expands a word which must be a non-negative integer, e.g. the argument
+/// of `break`.
+pub fn expand_number(shell: &mut Shell, word: &Word, what: &str) -> anyhow::Result<usize> {
+    let text = expand_word(shell, word)?;
+    text.parse()
+        .map_err(|_| anyhow!("{what}: {text}: numeric argument required"))
+}
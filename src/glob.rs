@@ -0,0 +1,512 @@
+//! Glob pattern matching used for pathname expansion.
+//!
+//! Patterns use `*` to match any string, `?` to match any character and
+//! `[...]` to match a character in a set. A backslash makes the following
+//! character match literally.
+//!
+//! With `extglob`, patterns can also use groups of alternatives separated by
+//! `|`: `?(p)` matches zero or one of them, `*(p)` zero or more, `+(p)` one
+//! or more, `@(p)` exactly one and `!(p)` anything except one of them. With
+//! `globstar`, a `**` path component matches any number of directories.
+
+use std::collections::HashMap;
+use std::fs::read_dir;
+use std::path::Path;
+
+use crate::options::ShellOptions;
+
+/// A compiled piece of a pattern.
+#[derive(Debug)]
+enum Item {
+    /// Matches a character exactly.
+    Char(char),
+
+    /// Matches any one character, `?`.
+    Any,
+
+    /// Matches any string, `*`.
+    Star,
+
+    /// Matches one character in, or not in, a set, e.g. `[a-z]`.
+    Set {
+        is_negated: bool,
+        ranges: Vec<SetItem>,
+    },
+
+    /// Matches a number of the alternatives in an extended pattern group,
+    /// e.g. `+(a|b)`, depending on its operator.
+    Group {
+        operator: char,
+        alternatives: Vec<Vec<Item>>,
+    },
+}
+
+/// A member of a bracket expression.
+#[derive(Debug)]
+enum SetItem {
+    Range(char, char),
+    Class(String),
+}
+
+impl SetItem {
+    fn contains(&self, c: char) -> bool {
+        match self {
+            Self::Range(low, high) => *low <= c && c <= *high,
+            Self::Class(class) => match class.as_ref() {
+                "alnum" => c.is_alphanumeric(),
+                "alpha" => c.is_alphabetic(),
+                "blank" => c == ' ' || c == '\t',
+                "cntrl" => c.is_control(),
+                "digit" => c.is_ascii_digit(),
+                "graph" => !c.is_whitespace() && !c.is_control(),
+                "lower" => c.is_lowercase(),
+                "print" => !c.is_control(),
+                "punct" => c.is_ascii_punctuation(),
+                "space" => c.is_whitespace(),
+                "upper" => c.is_uppercase(),
+                "xdigit" => c.is_ascii_hexdigit(),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Determines if a pattern has any unescaped special characters.
+fn has_wildcards(pattern: &str, is_extglob: bool) -> bool {
+    compile(pattern, is_extglob)
+        .iter()
+        .any(|item| !matches!(item, Item::Char(_)))
+}
+
+/// Determines if a whole text matches a pattern, which can use extended
+/// pattern groups if `extglob` is on.
+pub fn matches(pattern: &str, text: &str, is_extglob: bool) -> bool {
+    let items = compile(pattern, is_extglob);
+    let text: Vec<char> = text.chars().collect();
+    matches_items(&items, &text, false)
+}
+
+/// Removes the escaping backslashes from a pattern.
+pub fn unescape(pattern: &str) -> String {
+    let mut s = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => s.extend(chars.next()),
+            c => s.push(c),
+        }
+    }
+    s
+}
+
+/// Escapes a text so it matches itself as a pattern, including with
+/// `extglob`.
+pub fn escape(text: &str) -> String {
+    let mut s = String::new();
+    for c in text.chars() {
+        if matches!(
+            c,
+            '*' | '?' | '[' | ']' | '\\' | '@' | '+' | '!' | '(' | ')' | '|'
+        ) {
+            s.push('\\');
+        }
+        s.push(c);
+    }
+    s
+}
+
+/// Expands a pattern into the sorted pathnames which match it. Returns an
+/// empty vector if nothing matches. `dotglob`, `extglob`, `globstar` and
+/// `nocaseglob` change which names match.
+pub fn expand_pathname(pattern: &str, options: &ShellOptions) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec![String::from("/")], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    let components: Vec<&str> = rest.split('/').collect();
+    for (i, component) in components.iter().enumerate() {
+        let is_last = i + 1 == components.len();
+
+        if component.is_empty() {
+            // A trailing slash only matches directories.
+            if is_last {
+                paths.retain(|path| Path::new(path).is_dir());
+            }
+            continue;
+        }
+
+        let mut matched = Vec::new();
+        if *component == "**" && options.globstar {
+            for path in paths {
+                // `**` can match no directories at all, so `d/**` matches
+                // `d/` itself.
+                if !is_last || !path.is_empty() {
+                    matched.push(path.clone());
+                }
+                walk(&path, is_last, options, &mut matched);
+            }
+            paths = matched;
+            continue;
+        }
+
+        for path in paths {
+            if has_wildcards(component, options.extglob) {
+                matched.extend(
+                    matching_entries(&path, component, options)
+                        .into_iter()
+                        .map(|name| join(&path, &name, is_last)),
+                );
+            } else {
+                let path = join(&path, &unescape(component), is_last);
+                if Path::new(&path).symlink_metadata().is_ok() {
+                    matched.push(path);
+                }
+            }
+        }
+        paths = matched;
+    }
+
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Adds the directories below a directory to `paths`, recursively, for a
+/// `**` component. If it's the last component, other files are added too.
+/// Symbolic links aren't followed, so links which loop can't make it recurse
+/// forever.
+fn walk(directory: &str, is_last: bool, options: &ShellOptions, paths: &mut Vec<String>) {
+    let path = if directory.is_empty() { "." } else { directory };
+    let Ok(entries) = read_dir(path) else {
+        return;
+    };
+
+    let mut entries: Vec<(String, bool)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            let name = entry.file_name().into_string().ok()?;
+            Some((name, is_dir))
+        })
+        .filter(|(name, _)| options.dotglob || !name.starts_with('.'))
+        .collect();
+    entries.sort();
+
+    for (name, is_dir) in entries {
+        if is_dir {
+            let subdirectory = join(directory, &name, false);
+            paths.push(join(directory, &name, is_last));
+            walk(&subdirectory, is_last, options, paths);
+        } else if is_last {
+            paths.push(join(directory, &name, true));
+        }
+    }
+}
+
+/// Lists names in a directory which match a single path component pattern.
+/// Names starting with `.` only match a pattern which starts with `.`,
+/// unless `dotglob` is on.
+fn matching_entries(directory: &str, pattern: &str, options: &ShellOptions) -> Vec<String> {
+    let path = if directory.is_empty() { "." } else { directory };
+    let Ok(entries) = read_dir(path) else {
+        return Vec::new();
+    };
+
+    let items = compile(pattern, options.extglob);
+    let matches_hidden = options.dotglob || pattern.starts_with('.');
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| matches_hidden || !name.starts_with('.'))
+        .filter(|name| {
+            let chars: Vec<char> = name.chars().collect();
+            matches_items(&items, &chars, options.nocaseglob)
+        })
+        .collect()
+}
+
+/// Joins a directory and a name, adding a slash if it isn't the last
+/// component of a path.
+fn join(directory: &str, name: &str, is_last: bool) -> String {
+    let mut path = String::from(directory);
+    path.push_str(name);
+    if !is_last {
+        path.push('/');
+    }
+    path
+}
+
+/// Compiles a pattern into items. Extended pattern groups are only compiled
+/// with `extglob`.
+fn compile(pattern: &str, is_extglob: bool) -> Vec<Item> {
+    let chars: Vec<char> = pattern.chars().collect();
+    compile_chars(&chars, is_extglob)
+}
+
+/// Compiles the characters of a pattern into items.
+fn compile_chars(chars: &[char], is_extglob: bool) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let group = match is_extglob && chars.get(i + 1) == Some(&'(') {
+            true => compile_group(chars, i, is_extglob),
+            false => None,
+        };
+        if let Some((item, end)) = group {
+            items.push(item);
+            i = end + 1;
+            continue;
+        }
+
+        let item = match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                Item::Char(chars[i])
+            }
+            '*' => Item::Star,
+            '?' => Item::Any,
+            '[' => match compile_set(chars, i + 1) {
+                Some((item, end)) => {
+                    i = end;
+                    item
+                }
+                None => Item::Char('['),
+            },
+            c => Item::Char(c),
+        };
+        items.push(item);
+        i += 1;
+    }
+    items
+}
+
+/// Compiles an extended pattern group whose operator, e.g. `+`, is at the
+/// given index, followed by `(`. Returns the group and the index of its
+/// closing `)`, or `None` if it isn't one.
+fn compile_group(chars: &[char], start: usize, is_extglob: bool) -> Option<(Item, usize)> {
+    let operator = chars[start];
+    if !matches!(operator, '?' | '*' | '+' | '@' | '!') {
+        return None;
+    }
+
+    // Alternatives are separated by `|`s which aren't in nested groups.
+    let mut alternatives = Vec::new();
+    let mut alternative_start = start + 2;
+    let mut depth = 0;
+    let mut i = start + 2;
+    loop {
+        match chars.get(i)? {
+            '\\' => i += 1,
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            '|' if depth == 0 => {
+                alternatives.push(compile_chars(&chars[alternative_start..i], is_extglob));
+                alternative_start = i + 1;
+            }
+            ')' => {
+                alternatives.push(compile_chars(&chars[alternative_start..i], is_extglob));
+                let item = Item::Group {
+                    operator,
+                    alternatives,
+                };
+                return Some((item, i));
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// Compiles a bracket expression starting after its `[`. Returns the set
+/// and the index of its closing `]`, or `None` if it isn't closed.
+fn compile_set(chars: &[char], start: usize) -> Option<(Item, usize)> {
+    let mut i = start;
+    let is_negated = matches!(chars.get(i), Some('!' | '^'));
+    if is_negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let first = i;
+    loop {
+        let c = *chars.get(i)?;
+        match c {
+            ']' if i > first => return Some((Item::Set { is_negated, ranges }, i)),
+            '[' if chars.get(i + 1) == Some(&':') => {
+                let rest = &chars[i + 2..];
+                let end = rest.windows(2).position(|w| w == [':', ']'])?;
+                ranges.push(SetItem::Class(rest[..end].iter().collect()));
+                i += end + 4;
+                continue;
+            }
+            _ => {}
+        }
+
+        let low = if c == '\\' {
+            i += 1;
+            *chars.get(i)?
+        } else {
+            c
+        };
+        let high =
+            if chars.get(i + 1) == Some(&'-') && !matches!(chars.get(i + 2), None | Some(']')) {
+                i += 2;
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                *chars.get(i)?
+            } else {
+                low
+            };
+        ranges.push(SetItem::Range(low, high));
+        i += 1;
+    }
+}
+
+/// Matches compiled items against a whole text, optionally ignoring case.
+fn matches_items(items: &[Item], text: &[char], ignores_case: bool) -> bool {
+    if items.iter().any(|item| matches!(item, Item::Group { .. })) {
+        return GroupMatcher::new(text, ignores_case).matches(items, 0, text.len());
+    }
+
+    // The positions in the pattern and text to go back to when the last
+    // star should match one more character.
+    let mut backtrack: Option<(usize, usize)> = None;
+    let mut i = 0;
+    let mut j = 0;
+
+    while j < text.len() {
+        let is_match = match items.get(i) {
+            Some(Item::Star) => {
+                backtrack = Some((i, j));
+                i += 1;
+                continue;
+            }
+            Some(item) => matches_char(item, text[j], ignores_case),
+            None => false,
+        };
+
+        if is_match {
+            i += 1;
+            j += 1;
+        } else if let Some((star, position)) = backtrack {
+            i = star + 1;
+            j = position + 1;
+            backtrack = Some((star, position + 1));
+        } else {
+            return false;
+        }
+    }
+
+    items[i..].iter().all(|item| matches!(item, Item::Star))
+}
+
+/// Matches compiled items which include extended pattern groups against
+/// parts of a text. Groups can match text of any length, so each is tried
+/// against every length of text in turn. Results are remembered for each
+/// piece of the pattern and part of the text, so patterns with several
+/// stars and groups don't take exponential time.
+struct GroupMatcher<'a> {
+    text: &'a [char],
+    ignores_case: bool,
+
+    /// Results keyed by the address and length of the items, and the start
+    /// and end of the part of the text.
+    results: HashMap<(usize, usize, usize, usize), bool>,
+
+    /// Results of repeated matches keyed by the address of the
+    /// alternatives, and the start and end of the part of the text.
+    repeated_results: HashMap<(usize, usize, usize), bool>,
+}
+
+impl<'a> GroupMatcher<'a> {
+    fn new(text: &'a [char], ignores_case: bool) -> Self {
+        Self {
+            text,
+            ignores_case,
+            results: HashMap::new(),
+            repeated_results: HashMap::new(),
+        }
+    }
+
+    /// Determines if items match the text from `start` up to `end`.
+    fn matches(&mut self, items: &[Item], start: usize, end: usize) -> bool {
+        let key = (items.as_ptr() as usize, items.len(), start, end);
+        if let Some(&is_match) = self.results.get(&key) {
+            return is_match;
+        }
+        let is_match = self.matches_uncached(items, start, end);
+        self.results.insert(key, is_match);
+        is_match
+    }
+
+    fn matches_uncached(&mut self, items: &[Item], start: usize, end: usize) -> bool {
+        let Some((item, rest)) = items.split_first() else {
+            return start == end;
+        };
+
+        match item {
+            Item::Star => (start..=end).any(|middle| self.matches(rest, middle, end)),
+            Item::Group {
+                operator,
+                alternatives,
+            } => (start..=end).any(|middle| {
+                let matches_group = match operator {
+                    '?' => middle == start || self.matches_one(alternatives, start, middle),
+                    '*' => self.matches_repeated(alternatives, start, middle),
+                    '+' => middle > start && self.matches_repeated(alternatives, start, middle),
+                    '@' => self.matches_one(alternatives, start, middle),
+                    _ => !self.matches_one(alternatives, start, middle),
+                };
+                matches_group && self.matches(rest, middle, end)
+            }),
+            item => {
+                start < end
+                    && matches_char(item, self.text[start], self.ignores_case)
+                    && self.matches(rest, start + 1, end)
+            }
+        }
+    }
+
+    /// Determines if one of a group's alternatives matches part of the text.
+    fn matches_one(&mut self, alternatives: &[Vec<Item>], start: usize, end: usize) -> bool {
+        alternatives
+            .iter()
+            .any(|alternative| self.matches(alternative, start, end))
+    }
+
+    /// Determines if part of the text is made of any number of matches of a
+    /// group's alternatives, one after another.
+    fn matches_repeated(&mut self, alternatives: &[Vec<Item>], start: usize, end: usize) -> bool {
+        let key = (alternatives.as_ptr() as usize, start, end);
+        if let Some(&is_match) = self.repeated_results.get(&key) {
+            return is_match;
+        }
+        let is_match = start == end
+            || (start + 1..=end).any(|middle| {
+                self.matches_one(alternatives, start, middle)
+                    && self.matches_repeated(alternatives, middle, end)
+            });
+        self.repeated_results.insert(key, is_match);
+        is_match
+    }
+}
+
+/// Matches an item which matches a single character against one.
+fn matches_char(item: &Item, c: char, ignores_case: bool) -> bool {
+    match item {
+        Item::Char(expected) if ignores_case => expected.to_lowercase().eq(c.to_lowercase()),
+        Item::Char(expected) => *expected == c,
+        Item::Any => true,
+        Item::Set { is_negated, ranges } => {
+            let contains = |c| ranges.iter().any(|range| range.contains(c));
+            let is_in_set = match ignores_case {
+                true => c.to_lowercase().chain(c.to_uppercase()).any(contains),
+                false => contains(c),
+            };
+            is_in_set != *is_negated
+        }
+        Item::Star | Item::Group { .. } => false,
+    }
+}
@@ -1,224 +1,1468 @@
+mod alias;
+mod arith;
 mod ast;
+mod condition;
+mod declare;
 mod editing;
 mod error;
+mod expand;
+mod glob;
+mod options;
 mod parser;
+mod printf;
+mod read;
+mod reader;
 mod scanner;
+mod shell;
+mod streams;
 mod system;
+mod variable;
 
+use crate::alias::*;
 use crate::ast::*;
 use crate::editing::*;
+use crate::error::{error_status, is_fatal, is_incomplete, SyntaxError};
+use crate::expand::*;
 use crate::parser::*;
+use crate::reader::{CommandReader, StdinReader};
+use crate::scanner::scan_subscript;
+use crate::shell::*;
+use crate::streams::*;
 use crate::system::*;
-use rustyline::history::{History, SearchDirection};
+use crate::variable::Variable;
+use anyhow::anyhow;
+use rustyline::error::ReadlineError;
+use rustyline::history::{FileHistory, History, MemHistory, SearchDirection};
+use rustyline::{Editor, Helper};
+use std::fmt;
 use std::fs::File;
 use std::fs::OpenOptions;
-use std::io::{self, Cursor, Write};
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufReader, Cursor, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Stdio};
+use std::rc::Rc;
+
+/// Where the shell reads its commands from, given its command line.
+enum Mode {
+    /// Commands are read from the terminal with the line editor.
+    Interactive,
+
+    /// Commands are read from standard input without a prompt.
+    Stdin,
+
+    /// A command string given with `-c` is evaluated.
+    Command(String),
+
+    /// Commands are read from a script file.
+    Script(String),
+}
+
+/// Which startup files the shell reads, given its command line.
+#[derive(Default)]
+struct Startup {
+    /// A login shell reads the profiles, e.g. `~/.profile`.
+    is_login: bool,
+
+    /// In POSIX mode, an interactive shell reads the file named by `$ENV`
+    /// instead of its rc file.
+    is_posix: bool,
+
+    /// Whether `--noprofile` skips the profiles.
+    no_profile: bool,
+
+    /// Whether `--norc` skips the rc file.
+    no_rc: bool,
+
+    /// A file given with `--rcfile` to read instead of the rc file.
+    rc_file: Option<String>,
+}
 
 fn main() -> anyhow::Result<()> {
     let paths = get_path();
-    let mut editor = create_editor(&paths)?;
+    let mut shell = Shell::new();
+
+    // Errors are reported with the shell's own name, before a script's name
+    // replaces it as `$0`.
+    let program = shell.name.clone();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (mode, startup) = match parse_arguments(&mut shell, &program, &args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}: {}", program, e);
+            std::process::exit(2);
+        }
+    };
+
+    read_startup_files(&mut shell, &startup, &program);
+
+    let status = match mode {
+        Mode::Interactive => run_interactive(&mut shell, &paths)?,
+        Mode::Stdin => run_lines(&mut shell, StdinReader::new())?,
+        Mode::Command(command_text) => run_lines(&mut shell, Cursor::new(command_text))?,
+        Mode::Script(path) => match File::open(&path) {
+            Ok(file) => run_lines(&mut shell, BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                eprintln!("{}: {}: No such file or directory", program, path);
+                127
+            }
+            Err(e) => {
+                eprintln!("{}: {}: {}", program, path, e);
+                126
+            }
+        },
+    };
+
+    io::stdout().flush()?;
+    std::process::exit(status);
+}
+
+/// Parses the shell's command line arguments, which may set `$0` and the
+/// positional parameters. The program's name, e.g. `-sh`, can also make the
+/// shell a login shell or put it in POSIX mode. Returns where commands are
+/// read from and which startup files are read.
+fn parse_arguments(
+    shell: &mut Shell,
+    program: &str,
+    args: &[String],
+) -> anyhow::Result<(Mode, Startup)> {
+    let mut is_command = false;
+    let mut is_stdin = false;
+    let mut is_interactive = false;
+    let mut startup = Startup {
+        is_login: program.starts_with('-'),
+        is_posix: program_name(program) == "sh",
+        ..Startup::default()
+    };
+
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        match arg.as_str() {
+            "--" | "-" => {
+                i += 1;
+                break;
+            }
+            "--login" => startup.is_login = true,
+            "--noprofile" => startup.no_profile = true,
+            "--norc" => startup.no_rc = true,
+            "--posix" => startup.is_posix = true,
+            "--rcfile" => {
+                i += 1;
+                let rc_file = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("--rcfile: option requires an argument"))?;
+                startup.rc_file = Some(rc_file.clone());
+            }
+            _ if arg.starts_with("--") => Err(anyhow!("{arg}: invalid option"))?,
+            _ => {
+                // Other options are the same as `set`'s, e.g. `-e`, `+x` or
+                // `-o nounset`.
+                let (is_on, flags) = match (arg.strip_prefix('-'), arg.strip_prefix('+')) {
+                    (Some(flags), _) => (true, flags),
+                    (_, Some(flags)) => (false, flags),
+                    _ => break,
+                };
+                let sign = if is_on { '-' } else { '+' };
+                for flag in flags.chars() {
+                    match flag {
+                        'c' if is_on => is_command = true,
+                        'i' if is_on => is_interactive = true,
+                        'l' if is_on => startup.is_login = true,
+                        's' if is_on => is_stdin = true,
+                        'o' => {
+                            i += 1;
+                            let name = args
+                                .get(i)
+                                .ok_or_else(|| anyhow!("{sign}o: option requires an argument"))?;
+                            if !shell.options.set_name(name, is_on) {
+                                Err(anyhow!("{name}: invalid option name"))?;
+                            }
+                        }
+                        _ if shell.options.set_letter(flag, is_on) => {}
+                        _ => Err(anyhow!("{sign}{flag}: invalid option"))?,
+                    }
+                }
+            }
+        }
+        i += 1;
+    }
+
+    let mut operands = args[i..].iter().cloned();
+    let mode = if is_command {
+        let command_text = operands
+            .next()
+            .ok_or_else(|| anyhow!("-c: option requires an argument"))?;
+        if let Some(name) = operands.next() {
+            shell.name = name;
+        }
+        Mode::Command(command_text)
+    } else if is_stdin || i == args.len() {
+        // Like other shells, the shell is only interactive on a terminal,
+        // unless `-i` says otherwise.
+        if is_interactive || (io::stdin().is_terminal() && io::stderr().is_terminal()) {
+            Mode::Interactive
+        } else {
+            Mode::Stdin
+        }
+    } else {
+        let path = operands.next().unwrap_or_default();
+        shell.name = path.clone();
+        Mode::Script(path)
+    };
+
+    shell.positional = operands.collect();
+    shell.is_interactive = is_interactive || matches!(mode, Mode::Interactive);
+    shell.input_flag = match mode {
+        Mode::Command(_) => Some('c'),
+        Mode::Interactive | Mode::Stdin => Some('s'),
+        Mode::Script(_) => None,
+    };
+    Ok((mode, startup))
+}
+
+/// Gets the name a program was run with, without its directory or the `-`
+/// which marks a login shell.
+fn program_name(program: &str) -> &str {
+    let program = program.trim_start_matches('-');
+    program.rsplit('/').next().unwrap_or(program)
+}
+
+/// Reads and evaluates the startup files. A login shell reads `/etc/profile`
+/// and then `~/.profile`. An interactive shell which isn't a login shell reads
+/// its rc file, e.g. `~/.shrc` for a shell named `sh`. In POSIX mode, any
+/// interactive shell reads the file named by `$ENV` instead. Files which
+/// don't exist are skipped.
+fn read_startup_files(shell: &mut Shell, startup: &Startup, program: &str) {
+    let home = shell.get_variable("HOME");
+    let in_home = |name: &str| home.as_ref().map(|home| format!("{home}/{name}"));
+
+    let mut paths = Vec::new();
+    if startup.is_login && !startup.no_profile {
+        paths.push(Some(String::from("/etc/profile")));
+        paths.push(in_home(".profile"));
+    }
+    if shell.is_interactive {
+        if startup.is_posix {
+            paths.push(env_file(shell));
+        } else if !startup.is_login && !startup.no_rc {
+            let rc_file = in_home(&format!(".{}rc", program_name(program)));
+            paths.push(startup.rc_file.clone().or(rc_file));
+        }
+    }
+
+    let history = MemHistory::new();
+    let mut streams = Streams::standard();
+    for path in paths.into_iter().flatten() {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                continue;
+            }
+        };
+
+        // Startup files are sourced, so they can `return`.
+        shell.source_depth += 1;
+        if let Err(e) = eval_file(shell, &history, &mut streams, &path, file) {
+            eprintln!("{}", e);
+        }
+        shell.source_depth -= 1;
+        shell.jump = None;
+    }
+}
+
+/// Gets the file named by `$ENV`, after parameter and arithmetic expansion.
+fn env_file(shell: &mut Shell) -> Option<String> {
+    let env = shell.get_variable("ENV")?;
+    match scan_subscript(&env).and_then(|word| expand_word(shell, &word)) {
+        Ok(path) if !path.is_empty() => Some(path),
+        Ok(_) => None,
+        Err(e) => {
+            eprintln!("ENV: {}", e);
+            None
+        }
+    }
+}
+
+/// Reads commands from the terminal with the line editor and evaluates them
+/// until the end of input. Returns the exit status of the last command.
+fn run_interactive(shell: &mut Shell, paths: &[PathBuf]) -> anyhow::Result<i32> {
+    let mut editor = create_editor(paths)?;
+    if let Some(history_file) = shell.get_variable("HISTFILE") {
+        if Path::new(&history_file).exists() {
+            editor.load_history(&history_file)?;
+        }
+    }
+
+    'commands: loop {
+        let mut command_text = match editor.readline("$ ") {
+            Ok(command_text) => command_text,
+            Err(ReadlineError::Eof) => return Ok(shell.status),
+            // Ctrl-C abandons the line being edited.
+            Err(ReadlineError::Interrupted) => continue,
+            Err(e) => Err(e)?,
+        };
+
+        // While the command is incomplete, e.g. after a trailing `|` or inside
+        // an `if` command, more lines are read with the `PS2` prompt.
+        while matches!(parse(&command_text, shell.shell_options.extglob), Err(e) if is_incomplete(&e))
+        {
+            let prompt = shell
+                .get_variable("PS2")
+                .unwrap_or_else(|| String::from("> "));
+            match editor.readline(&prompt) {
+                Ok(line) => {
+                    command_text.push('\n');
+                    command_text.push_str(&line);
+                }
+                // Evaluating the command reports it as incomplete.
+                Err(ReadlineError::Eof) => break,
+                Err(ReadlineError::Interrupted) => continue 'commands,
+                Err(e) => Err(e)?,
+            }
+        }
+        editor.add_history_entry(command_text.as_str())?;
+
+        if let Err(e) = eval(shell, editor.history(), &command_text) {
+            eprintln!("{}", e);
+        }
+        if let Err(e) = save_history(shell, &mut editor) {
+            eprintln!("{}", e);
+        }
+        if let Some(helper) = editor.helper_mut() {
+            helper.set_aliases(shell.aliases.keys().cloned().collect());
+        }
+    }
+}
+
+/// Writes the history to `$HISTFILE`, if it's set. It's written after each
+/// command, so it's kept however the shell exits. With `histappend`, new
+/// lines are appended rather than replacing the file.
+fn save_history<H: Helper>(
+    shell: &Shell,
+    editor: &mut Editor<H, FileHistory>,
+) -> anyhow::Result<()> {
+    let Some(history_file) = shell.get_variable("HISTFILE") else {
+        return Ok(());
+    };
+    if shell.shell_options.histappend && Path::new(&history_file).exists() {
+        editor.append_history(&history_file)?;
+    } else {
+        editor.save_history(&history_file)?;
+    }
+    Ok(())
+}
+
+/// Reads commands from lines of text, e.g. of a script, and evaluates them.
+/// Lines are read until they make complete commands, which are evaluated
+/// before reading more. Returns the exit status of the last command.
+fn run_lines<R: BufRead>(shell: &mut Shell, reader: R) -> anyhow::Result<i32> {
+    let history = MemHistory::new();
+    let mut commands = CommandReader::new(reader);
     loop {
-        let command_text = editor.readline("$ ")?;
-        if let Err(e) = eval(&paths, editor.history(), &command_text) {
+        commands.set_extglob(shell.shell_options.extglob);
+        let Some(command_text) = commands.next() else {
+            break;
+        };
+        let (command_text, _) = command_text?;
+        if let Err(e) = eval(shell, &history, &command_text) {
             eprintln!("{}", e);
         }
     }
+    Ok(shell.status)
+}
+
+fn eval<H>(shell: &mut Shell, history: &H, command_text: &str) -> anyhow::Result<()>
+where
+    H: History,
+{
+    let first_line = shell.lines_read + 1;
+    shell.lines_read += command_text.lines().count().max(1);
+    if shell.options.verbose {
+        eprintln!("{command_text}");
+    }
+
+    let command_text = match shell.shell_options.expand_aliases {
+        true => expand_aliases(&shell.aliases, command_text, shell.shell_options.extglob),
+        false => command_text.to_string(),
+    };
+    let list = match parse_at_line(&command_text, first_line, shell.shell_options.extglob) {
+        Ok(list) => list,
+        Err(e) => {
+            shell.status = 2;
+            return Err(e);
+        }
+    };
+
+    let mut streams = Streams::standard();
+    eval_list(shell, history, &mut streams, &list)?;
+
+    // A `break`, `continue` or `return` can't reach past the command text.
+    shell.jump = None;
+
+    Ok(())
+}
+
+/// Evaluates a list of commands. Returns the exit status of the last command.
+fn eval_list<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &mut Streams,
+    list: &List,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    for and_or in list {
+        if shell.options.noexec && !shell.is_interactive {
+            break;
+        }
+        match and_or.is_async {
+            true => eval_async(shell, history, streams, and_or)?,
+            false => eval_and_or(shell, history, streams, and_or)?,
+        };
+        if shell.jump.is_some() {
+            break;
+        }
+    }
+    Ok(shell.status)
 }
 
-fn eval<H>(paths: &[PathBuf], history: &H, command_text: &str) -> anyhow::Result<()>
+/// Evaluates pipelines joined by `&&` and `||`.
+fn eval_and_or<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &mut Streams,
+    and_or: &AndOr,
+) -> anyhow::Result<i32>
 where
     H: History,
 {
-    let pipeline = parse(command_text)?;
-    let n = pipeline.len();
-
-    // This has the child process for each item command in the pipeline. If the
-    // command was a built-in then `None` is pushed.
-    let mut children = Vec::<Option<Child>>::new();
-
-    // If the previous command was a built-in, this holds its output buffer.
-    let mut built_in_out = None;
-
-    for (i, command) in pipeline.iter().enumerate() {
-        let is_first = i == 0;
-        let is_last = i + 1 == n;
-
-        match command {
-            Command::BuiltIn(command) => {
-                // If there is any output for previous command in pipeline
-                // we should discard it.
-                let _ = built_in_out.take();
-
-                let out = eval_built_in_command(paths, history, command)?;
-                if is_last {
-                    io::stdout().write_all(&out)?;
-                } else {
-                    built_in_out.replace(out);
+    // Every pipeline but the last has its exit status tested.
+    let pipelines: Vec<_> = std::iter::once((None, &and_or.first))
+        .chain(and_or.rest.iter().map(|(c, p)| (Some(c), p)))
+        .collect();
+    let last = pipelines.len() - 1;
+
+    let mut status = 0;
+    for (i, (connector, pipeline)) in pipelines.into_iter().enumerate() {
+        if shell.jump.is_some() {
+            break;
+        }
+
+        let should_run = match connector {
+            None => true,
+            Some(Connector::And) => status == 0,
+            Some(Connector::Or) => status != 0,
+        };
+        if !should_run {
+            continue;
+        }
+        status = match i == last {
+            true => eval_pipeline(shell, history, streams, pipeline)?,
+            false => as_condition(shell, |shell| {
+                eval_pipeline(shell, history, streams, pipeline)
+            })?,
+        };
+    }
+    Ok(status)
+}
+
+/// Evaluates a pipeline. Its exit status becomes the shell's `$?`.
+fn eval_pipeline<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &mut Streams,
+    pipeline: &Pipeline,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let mut eval_commands = |shell: &mut Shell| match &pipeline.commands[..] {
+        [command] => eval_command(shell, history, streams, command),
+        commands => eval_piped_commands(shell, history, streams, commands),
+    };
+
+    let status = if pipeline.is_negated {
+        (as_condition(shell, eval_commands)? == 0) as i32
+    } else {
+        eval_commands(shell)?
+    };
+
+    shell.status = status;
+
+    // Other compound commands only fail when a command in them does, which
+    // would have already exited.
+    let is_compound = matches!(
+        &pipeline.commands[..],
+        [Command::ArithmeticFor(_)
+            | Command::Case(_)
+            | Command::For(_)
+            | Command::Group(_)
+            | Command::If(_)
+            | Command::While(_)]
+    );
+    if status != 0
+        && shell.options.errexit
+        && shell.condition_depth == 0
+        && !pipeline.is_negated
+        && !is_compound
+    {
+        io::stdout().flush()?;
+        std::process::exit(status);
+    }
+
+    Ok(status)
+}
+
+/// Evaluates a command whose exit status is tested, e.g. an `if` condition,
+/// so `errexit` doesn't apply to the commands in it.
+fn as_condition<F>(shell: &mut Shell, eval_condition: F) -> anyhow::Result<i32>
+where
+    F: FnOnce(&mut Shell) -> anyhow::Result<i32>,
+{
+    shell.condition_depth += 1;
+    let status = eval_condition(shell);
+    shell.condition_depth -= 1;
+    status
+}
+
+/// Evaluates commands joined by pipes. Every command but the last runs in a
+/// forked child process whose output goes through a pipe to the next
+/// command, so the commands run at the same time. The last command runs in
+/// the shell. Returns its exit status.
+fn eval_piped_commands<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &mut Streams,
+    commands: &[Command],
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let (last, rest) = commands.split_last().expect("a pipeline has commands");
+
+    // Process ids of the forked children.
+    let mut pids = Vec::new();
+
+    // The input of the next command in the pipeline.
+    let mut stdin = streams.stdin.try_clone()?;
+
+    for command in rest {
+        let (reader, writer) = pipe()?;
+        let member = Streams {
+            stdin,
+            stdout: Output::File(writer),
+            stderr: streams.stderr.try_clone()?,
+        };
+
+        // The child closes its copy of the pipe's read end, so it gets
+        // `SIGPIPE` rather than blocking once the next command has exited.
+        let mut next_stdin = Some(reader);
+        let pid = eval_forked(shell, &member, |shell, streams| {
+            drop(next_stdin.take());
+            eval_command(shell, history, streams, command)
+        })?;
+        pids.push(pid);
+
+        // The next command only sees the end of its input once every write
+        // end of the pipe, including the shell's, is closed.
+        drop(member);
+        stdin = Input::File(next_stdin.expect("the shell keeps the read end"));
+    }
+
+    let mut member = Streams {
+        stdin,
+        stdout: streams.stdout.try_clone()?,
+        stderr: streams.stderr.try_clone()?,
+    };
+    let status = eval_command(shell, history, &mut member, last)?;
+
+    // Drop the last pipe's read end so earlier commands can't block on it.
+    drop(member);
+
+    for pid in pids {
+        wait_for_pid(pid)?;
+    }
+
+    Ok(status)
+}
+
+/// Evaluates a command. An error, e.g. a command not being found, is
+/// reported and gives the command a failing exit status.
+fn eval_command<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &mut Streams,
+    command: &Command,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let result = match command {
+        Command::BuiltIn(command) => eval_built_in_command(shell, history, streams, command),
+        Command::External(command) => eval_external_command(shell, history, streams, command),
+        Command::Arithmetic(command) => eval_arithmetic_command(shell, streams, command),
+        Command::ArithmeticFor(command) => eval_arithmetic_for(shell, history, streams, command),
+        Command::Case(command) => eval_case(shell, history, streams, command),
+        Command::Conditional(command) => eval_conditional_command(shell, streams, command),
+        Command::For(command) => eval_for(shell, history, streams, command),
+        Command::FunctionDefinition(definition) => {
+            let body = Rc::clone(&definition.body);
+            shell.functions.insert(definition.name.clone(), body);
+            Ok(0)
+        }
+        Command::Group(command) => eval_group(shell, history, streams, command),
+        Command::If(command) => eval_if(shell, history, streams, command),
+        Command::Subshell(command) => eval_subshell(shell, history, streams, command),
+        Command::While(command) => eval_while(shell, history, streams, command),
+    };
+
+    match result {
+        Ok(status) => Ok(status),
+        Err(e) => {
+            writeln!(streams.stderr, "{e}")?;
+            if is_fatal(&e) && !shell.is_interactive {
+                io::stdout().flush()?;
+                std::process::exit(error_status(&e));
+            }
+            Ok(error_status(&e))
+        }
+    }
+}
+
+/// Evaluates an arithmetic command, which succeeds if the expression's value
+/// is non-zero.
+fn eval_arithmetic_command(
+    shell: &mut Shell,
+    streams: &Streams,
+    command: &ArithmeticCommand,
+) -> anyhow::Result<i32> {
+    redirect(shell, streams, &command.redirections)?;
+    let value = arith::evaluate(shell, &command.expression)?;
+    Ok((value == 0) as i32)
+}
+
+/// Evaluates a C-style for loop.
+fn eval_arithmetic_for<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &Streams,
+    command: &ArithmeticForCommand,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let mut streams = redirect(shell, streams, &command.redirections)?;
+    arith::evaluate(shell, &command.init)?;
+
+    in_loop(shell, |shell| {
+        let mut status = 0;
+        loop {
+            // An empty condition is always true.
+            if !command.condition.trim().is_empty()
+                && arith::evaluate(shell, &command.condition)? == 0
+            {
+                break;
+            }
+
+            status = eval_list(shell, history, &mut streams, &command.body)?;
+            if should_exit_loop(shell) {
+                break;
+            }
+
+            arith::evaluate(shell, &command.update)?;
+        }
+        Ok(status)
+    })
+}
+
+/// Evaluates a case command. Returns the exit status of the last command run,
+/// or zero if no pattern matched.
+fn eval_case<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &Streams,
+    command: &CaseCommand,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let mut streams = redirect(shell, streams, &command.redirections)?;
+    let word = expand_word(shell, &command.word)?;
+
+    let mut status = 0;
+
+    // Set when the previous item ended with `;&`.
+    let mut is_falling_through = false;
+
+    for item in &command.items {
+        if !is_falling_through && !case_item_matches(shell, item, &word)? {
+            continue;
+        }
+
+        status = eval_list(shell, history, &mut streams, &item.body)?;
+        if shell.jump.is_some() {
+            break;
+        }
+
+        match item.terminator {
+            CaseTerminator::Break => break,
+            CaseTerminator::FallThrough => is_falling_through = true,
+            CaseTerminator::Continue => is_falling_through = false,
+        }
+    }
+
+    Ok(status)
+}
+
+/// Determines if a word matches any of a case item's patterns.
+fn case_item_matches(shell: &mut Shell, item: &CaseItem, word: &str) -> anyhow::Result<bool> {
+    for pattern in &item.patterns {
+        let pattern = expand_pattern(shell, pattern)?;
+        if glob::matches(&pattern, word, shell.shell_options.extglob) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Evaluates a conditional command, which succeeds if its expression is true.
+fn eval_conditional_command(
+    shell: &mut Shell,
+    streams: &Streams,
+    command: &ConditionalCommand,
+) -> anyhow::Result<i32> {
+    let mut streams = redirect(shell, streams, &command.redirections)?;
+    let is_true = conditional_is_true(shell, &mut streams.stderr, &command.expression)?;
+    Ok(!is_true as i32)
+}
+
+/// Determines if a conditional expression is true. Each test is traced as
+/// it's evaluated, since `&&` and `||` may skip some of them.
+fn conditional_is_true<TErr: Write>(
+    shell: &mut Shell,
+    stderr: &mut TErr,
+    expression: &ConditionalExpression,
+) -> anyhow::Result<bool> {
+    let is_true = match expression {
+        ConditionalExpression::Word(word) => {
+            let text = expand_word(shell, word)?;
+            trace_line(shell, stderr, &format!("[[ {} ]]", quote_special(&text)))?;
+            !text.is_empty()
+        }
+        ConditionalExpression::Unary(operator, operand) => {
+            let operand = expand_word(shell, operand)?;
+            let line = format!("[[ {operator} {} ]]", quote_special(&operand));
+            trace_line(shell, stderr, &line)?;
+            condition::unary_test(operator, &operand)?
+        }
+        ConditionalExpression::Binary(left, operator, right) => {
+            let left = expand_word(shell, left)?;
+            // Unquoted characters on the right of `==`, `!=` and `=~` are
+            // special, so it's a pattern or a regular expression.
+            let right = match operator.as_ref() {
+                "=" | "==" | "!=" => expand_pattern(shell, right)?,
+                "=~" => expand_regex(shell, right)?,
+                _ => expand_word(shell, right)?,
+            };
+            let line = format!("[[ {} {operator} {right} ]]", quote_special(&left));
+            trace_line(shell, stderr, &line)?;
+
+            let extglob = shell.shell_options.extglob;
+            match operator.as_ref() {
+                "=" | "==" => glob::matches(&right, &left, extglob),
+                "!=" => !glob::matches(&right, &left, extglob),
+                "=~" => {
+                    let groups = condition::regex_match(&right, &left)?;
+                    let is_match = groups.is_some();
+                    let elements = groups.unwrap_or_default().into_iter();
+                    shell.set_array("BASH_REMATCH", elements.map(|g| (None, g)).collect())?;
+                    is_match
                 }
+                // Integer operands are arithmetic expressions.
+                "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge" => {
+                    let left = arith::evaluate(shell, &left)?;
+                    let right = arith::evaluate(shell, &right)?;
+                    condition::binary_test(&left.to_string(), operator, &right.to_string())?
+                }
+                _ => condition::binary_test(&left, operator, &right)?,
+            }
+        }
+        ConditionalExpression::Not(expression) => !conditional_is_true(shell, stderr, expression)?,
+        ConditionalExpression::And(left, right) => {
+            conditional_is_true(shell, stderr, left)? && conditional_is_true(shell, stderr, right)?
+        }
+        ConditionalExpression::Or(left, right) => {
+            conditional_is_true(shell, stderr, left)? || conditional_is_true(shell, stderr, right)?
+        }
+        ConditionalExpression::Group(expression) => conditional_is_true(shell, stderr, expression)?,
+    };
+    Ok(is_true)
+}
+
+/// Evaluates a for loop over words or the positional parameters.
+fn eval_for<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &Streams,
+    command: &ForCommand,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let mut streams = redirect(shell, streams, &command.redirections)?;
+    let items = match &command.words {
+        Some(words) => expand_words(shell, words)?,
+        None => shell.positional.clone(),
+    };
 
-                // Built-ins don't create child processes.
-                children.push(None);
+    in_loop(shell, |shell| {
+        let mut status = 0;
+        for item in items {
+            shell.set_variable(&command.name, item)?;
+            status = eval_list(shell, history, &mut streams, &command.body)?;
+            if should_exit_loop(shell) {
+                break;
             }
+        }
+        Ok(status)
+    })
+}
 
-            Command::External(command) => {
-                let stdin = if is_first {
-                    Stdio::inherit()
-                } else if let Some(last_child) = &mut children[i - 1] {
-                    last_child
-                        .stdout
-                        .take()
-                        .map(Stdio::from)
-                        .unwrap_or_else(Stdio::piped)
-                } else {
-                    Stdio::piped()
-                };
+/// Evaluates a brace group in the current shell.
+fn eval_group<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &Streams,
+    command: &GroupCommand,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let mut streams = redirect(shell, streams, &command.redirections)?;
+    eval_list(shell, history, &mut streams, &command.body)
+}
 
-                let stdout = if is_last {
-                    Stdio::inherit()
-                } else {
-                    Stdio::piped()
-                };
+/// Evaluates a subshell in a forked child process and waits for it to exit.
+fn eval_subshell<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &Streams,
+    command: &SubshellCommand,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let streams = redirect(shell, streams, &command.redirections)?;
+    let pid = eval_forked(shell, &streams, |shell, streams| {
+        eval_list(shell, history, streams, &command.body)
+    })?;
+    wait_for_pid(pid)
+}
 
-                let mut command = eval_external_command(command, stdin, stdout)?;
-                let mut child = spawn_command(&mut command)?;
+/// Evaluates the commands of a command substitution in a forked child
+/// process. Returns their output without trailing newlines. Their exit
+/// status becomes the shell's.
+fn eval_command_substitution(shell: &mut Shell, command_text: &str) -> anyhow::Result<String> {
+    let list = parse_at_line(command_text, shell.line_number, shell.shell_options.extglob)?;
+    let mut streams = Streams::standard();
+    streams.stdout = Output::buffer();
+    let pid = eval_forked(shell, &streams, |shell, streams| {
+        eval_list(shell, &MemHistory::new(), streams, &list)
+    })?;
+    shell.status = wait_for_pid(pid)?;
+    shell.substitution_status = Some(shell.status);
+
+    let output = streams.stdout.take_buffer();
+    let output = String::from_utf8_lossy(&output);
+    Ok(output.trim_end_matches('\n').to_string())
+}
 
-                if let Some(buf) = built_in_out.take() {
-                    if let Some(mut stdin) = child.stdin.take() {
-                        stdin.write_all(&buf)?;
-                        stdin.flush()?;
+/// Evaluates an and-or list which ends with `&` in a forked child process,
+/// without waiting for it. Its process id is kept for `$!`.
+fn eval_async<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &Streams,
+    and_or: &AndOr,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let pid = eval_forked(shell, streams, |shell, streams| {
+        // Without job control, commands in the background don't read the
+        // shell's stdin.
+        if matches!(streams.stdin, Input::Inherit) {
+            streams.stdin = Input::File(File::open("/dev/null")?);
+        }
+        eval_and_or(shell, history, streams, and_or)
+    })?;
+    shell.background_pid = Some(pid as u32);
+    shell.status = 0;
+    Ok(0)
+}
+
+/// Evaluates commands in a forked child process, which exits with their exit
+/// status. Returns the child's process id once any output it writes to a
+/// buffer has been read.
+fn eval_forked<F>(shell: &mut Shell, streams: &Streams, eval: F) -> anyhow::Result<libc::pid_t>
+where
+    F: FnOnce(&mut Shell, &mut Streams) -> anyhow::Result<i32>,
+{
+    let mut streams = streams.try_clone()?;
+
+    // The child can't share the shell's buffers, so it gets pipes instead.
+    let input_pipe = match &streams.stdin {
+        Input::Buffer(_) => Some(pipe()?),
+        _ => None,
+    };
+    let output_pipe = match &streams.stdout {
+        Output::Buffer(_) => Some(pipe()?),
+        _ => None,
+    };
+
+    let pid = match fork()? {
+        Fork::Child => {
+            let mut streams = Streams {
+                stdin: match input_pipe {
+                    Some((reader, writer)) => {
+                        drop(writer);
+                        Input::File(reader)
+                    }
+                    None => streams.stdin,
+                },
+                stdout: match output_pipe {
+                    Some((reader, writer)) => {
+                        drop(reader);
+                        Output::File(writer)
                     }
+                    None => streams.stdout,
+                },
+                stderr: streams.stderr,
+            };
+            let status = match eval(shell, &mut streams) {
+                Ok(status) => status,
+                Err(e) => {
+                    let _ = writeln!(streams.stderr, "{e}");
+                    error_status(&e)
                 }
+            };
+            let _ = streams.stdout.flush();
+            std::process::exit(status);
+        }
+        Fork::Parent(pid) => pid,
+    };
 
-                children.push(Some(child));
-            }
+    if let Some((reader, mut writer)) = input_pipe {
+        drop(reader);
+        let bytes = streams.stdin.read_buffer();
+        std::thread::spawn(move || writer.write_all(&bytes));
+    }
+    if let Some((mut reader, writer)) = output_pipe {
+        // The child's output only ends once every write end is closed.
+        drop(writer);
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        streams.stdout.write_all(&bytes)?;
+    }
+
+    Ok(pid)
+}
+
+/// Evaluates an if command.
+fn eval_if<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &Streams,
+    command: &IfCommand,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let mut streams = redirect(shell, streams, &command.redirections)?;
+
+    for (condition, body) in &command.branches {
+        let status = as_condition(shell, |shell| {
+            eval_list(shell, history, &mut streams, condition)
+        })?;
+        if shell.jump.is_some() {
+            return Ok(status);
+        }
+        if status == 0 {
+            return eval_list(shell, history, &mut streams, body);
         }
     }
 
-    for child in children.iter_mut().flatten() {
-        child.wait()?;
+    match &command.else_body {
+        Some(body) => eval_list(shell, history, &mut streams, body),
+        None => Ok(0),
     }
+}
 
-    Ok(())
+/// Evaluates a while or until loop.
+fn eval_while<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &Streams,
+    command: &WhileCommand,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let mut streams = redirect(shell, streams, &command.redirections)?;
+
+    in_loop(shell, |shell| {
+        let mut status = 0;
+        loop {
+            let condition = as_condition(shell, |shell| {
+                eval_list(shell, history, &mut streams, &command.condition)
+            })?;
+            if should_exit_loop(shell) || (condition == 0) == command.is_until {
+                break;
+            }
+
+            status = eval_list(shell, history, &mut streams, &command.body)?;
+            if should_exit_loop(shell) {
+                break;
+            }
+        }
+        Ok(status)
+    })
+}
+
+/// Runs a loop, keeping track of how many loops `break` and `continue` can
+/// exit.
+fn in_loop<F>(shell: &mut Shell, run_loop: F) -> anyhow::Result<i32>
+where
+    F: FnOnce(&mut Shell) -> anyhow::Result<i32>,
+{
+    shell.loop_depth += 1;
+    let status = run_loop(shell);
+    shell.loop_depth -= 1;
+    status
+}
+
+/// Handles a pending `break` or `continue` at the end of a loop iteration.
+/// Returns true if the loop should stop.
+fn should_exit_loop(shell: &mut Shell) -> bool {
+    match shell.jump.take() {
+        Some(Jump::Break(n)) => {
+            if n > 1 {
+                shell.jump = Some(Jump::Break(n - 1));
+            }
+            true
+        }
+        Some(Jump::Continue(n)) if n > 1 => {
+            shell.jump = Some(Jump::Continue(n - 1));
+            true
+        }
+        Some(Jump::Continue(_)) | None => false,
+        Some(Jump::Return) => {
+            shell.jump = Some(Jump::Return);
+            true
+        }
+    }
 }
 
-/// Evaluates a built in command. Returns stdout contents, if any.
+/// Evaluates a built in command. Returns its exit status. A function with
+/// the name of a built-in other than a special one is called instead.
 fn eval_built_in_command<H>(
-    paths: &[PathBuf],
+    shell: &mut Shell,
     history: &H,
+    streams: &Streams,
     built_in_command: &BuiltInCommand,
-) -> anyhow::Result<Vec<u8>>
+) -> anyhow::Result<i32>
 where
     H: History,
 {
-    match &built_in_command.redirection {
-        Redirection::StdOut {
-            filename,
-            is_append,
-        } => {
-            let mut stdout = open_file(filename, *is_append)?;
-            let mut stderr = io::stderr();
-            eval_built_in(
-                paths,
-                history,
-                &mut stdout,
-                &mut stderr,
-                &built_in_command.built_in,
-            )?;
-            Ok(Vec::new())
-        }
+    shell.line_number = built_in_command.line;
+    let mut streams = redirect(shell, streams, &built_in_command.redirections)?;
+    let built_in = &built_in_command.built_in;
 
-        Redirection::StdErr {
-            filename,
-            is_append,
-        } => {
-            let mut stdout = Cursor::new(Vec::new());
-            let mut stderr = open_file(filename, *is_append)?;
-            eval_built_in(
-                paths,
-                history,
-                &mut stdout,
-                &mut stderr,
-                &built_in_command.built_in,
-            )?;
-            Ok(stdout.into_inner())
-        }
+    // Arguments are expanded before the assignments before the name, so
+    // they don't see the assigned values.
+    if let Some(body) = overriding_function(shell, built_in.name()) {
+        let words: Vec<Word> = built_in.args().into_iter().cloned().collect();
+        let mut args = vec![built_in.name().to_string()];
+        args.extend(expand_words(shell, &words)?);
+        let variables = expand_assignments(shell, &built_in_command.assignments)?;
+        trace(shell, &mut streams.stderr, &args[0], &args[1..])?;
+        return with_variables(shell, variables, |shell| {
+            call_function(shell, history, &streams, &body, &args)
+        });
+    }
 
-        Redirection::None => {
-            let mut stdout = Cursor::new(Vec::new());
-            let mut stderr = io::stderr();
-            eval_built_in(
-                paths,
-                history,
-                &mut stdout,
-                &mut stderr,
-                &built_in_command.built_in,
-            )?;
-            Ok(stdout.into_inner())
+    let built_in = expand_built_in(shell, built_in)?;
+    let variables = expand_assignments(shell, &built_in_command.assignments)?;
+    eval_built_in_with_variables(shell, history, &mut streams, variables, &built_in)
+}
+
+/// Gets the body of a function which is found before any built-in with the
+/// given name, i.e. unless it's a special built-in.
+fn overriding_function(shell: &Shell, name: &str) -> Option<Rc<Command>> {
+    match SPECIAL_BUILT_INS.contains(&name) {
+        true => None,
+        false => shell.functions.get(name).cloned(),
+    }
+}
+
+/// Evaluates a built-in with variables set while it runs. Returns its exit
+/// status.
+fn eval_built_in_with_variables<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &mut Streams,
+    variables: Vec<(String, String)>,
+    built_in: &BuiltIn,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let status = with_variables(shell, variables, |shell| match built_in {
+        BuiltIn::Dot(args) => source(shell, history, streams, ".", args),
+        BuiltIn::Source(args) => source(shell, history, streams, "source", args),
+        built_in => eval_built_in(
+            shell,
+            history,
+            &mut streams.stdin,
+            &mut streams.stdout,
+            &mut streams.stderr,
+            built_in,
+        ),
+    })?;
+    streams.stdout.flush()?;
+    Ok(status)
+}
+
+/// Runs a command with variables set temporarily, e.g. by assignments before
+/// a built-in's name. The variables' previous values are restored after.
+fn with_variables<F>(
+    shell: &mut Shell,
+    variables: Vec<(String, String)>,
+    run: F,
+) -> anyhow::Result<i32>
+where
+    F: FnOnce(&mut Shell) -> anyhow::Result<i32>,
+{
+    let saved: Vec<(String, Option<Variable>)> = variables
+        .iter()
+        .map(|(name, _)| (name.clone(), shell.save_variable(name)))
+        .collect();
+
+    let mut result = Ok(());
+    for (name, value) in variables {
+        result = shell.set_variable(&name, value);
+        if result.is_err() {
+            break;
         }
+        shell.declare_variable(&name).attributes.is_exported = true;
+    }
+
+    let status = result.and_then(|()| run(shell));
+
+    for (name, variable) in saved.into_iter().rev() {
+        shell.restore_variable(&name, variable);
     }
+    status
 }
 
 /// Evaluates a built in command.
 fn eval_built_in<H, TOut: Write, TErr: Write>(
-    paths: &[PathBuf],
+    shell: &mut Shell,
     history: &H,
+    stdin: &mut Input,
     stdout: &mut TOut,
     stderr: &mut TErr,
     built_in: &BuiltIn,
-) -> anyhow::Result<()>
+) -> anyhow::Result<i32>
 where
     H: History,
 {
     match built_in {
-        BuiltIn::Echo(args) => {
-            if !args.is_empty() {
-                write!(stdout, "{}", args[0])?;
-                for arg in args.iter().skip(1) {
-                    write!(stdout, " {}", arg)?;
+        BuiltIn::Bracket(args) => {
+            let args = expand_words(shell, args)?;
+            trace(shell, stderr, "[", &args)?;
+            return condition::test("[", &args, stderr);
+        }
+        BuiltIn::Alias(args) => {
+            let args = expand_words(shell, args)?;
+            trace(shell, stderr, "alias", &args)?;
+            if args.is_empty() {
+                for (name, value) in &shell.aliases {
+                    writeln!(stdout, "alias {}={}", name, quote(value))?;
+                }
+            }
+            let mut status = 0;
+            for arg in args {
+                match arg.split_once('=') {
+                    Some((name, _)) if !is_alias_name(name) => {
+                        writeln!(stderr, "alias: `{name}': invalid alias name")?;
+                        status = 1;
+                    }
+                    Some((name, value)) => {
+                        shell.aliases.insert(name.to_string(), value.to_string());
+                    }
+                    None => match shell.aliases.get(&arg) {
+                        Some(value) => writeln!(stdout, "alias {}={}", arg, quote(value))?,
+                        None => {
+                            writeln!(stderr, "alias: {arg}: not found")?;
+                            status = 1;
+                        }
+                    },
                 }
             }
-            writeln!(stdout)?;
+            return Ok(status);
+        }
+        BuiltIn::Break(count) | BuiltIn::Continue(count) => {
+            let name = match built_in {
+                BuiltIn::Break(_) => "break",
+                _ => "continue",
+            };
+            let count = match count {
+                Some(count) => expand_number(shell, count, name)?,
+                None => 1,
+            };
+            trace(shell, stderr, name, &[count])?;
+            if count == 0 {
+                writeln!(stderr, "{name}: {count}: loop count out of range")?;
+                return Ok(1);
+            }
+            if shell.loop_depth == 0 {
+                writeln!(
+                    stderr,
+                    "{name}: only meaningful in a `for', `while', or `until' loop"
+                )?;
+                return Ok(0);
+            }
+            let count = count.min(shell.loop_depth);
+            shell.jump = Some(match built_in {
+                BuiltIn::Break(_) => Jump::Break(count),
+                _ => Jump::Continue(count),
+            });
+        }
+        BuiltIn::Declare(args) | BuiltIn::Typeset(args) => {
+            let name = match built_in {
+                BuiltIn::Declare(_) => "declare",
+                _ => "typeset",
+            };
+            let args = expand_declaration_words(shell, args)?;
+            trace(shell, stderr, name, &args)?;
+            return declare::declare(shell, name, &args, stdout, stderr);
+        }
+        // Sourcing needs all the command's streams, so it's evaluated by
+        // `eval_built_in_command` instead.
+        BuiltIn::Dot(_) | BuiltIn::Source(_) => unreachable!(),
+        BuiltIn::Echo(args) => {
+            let args = expand_words(shell, args)?;
+            trace(shell, stderr, "echo", &args)?;
+            echo(shell, &args, stdout)?;
         }
-        BuiltIn::Cd(path) if path == "~" => match std::env::home_dir() {
-            Some(home) => change_directory(&home)?,
-            None => writeln!(stderr, "cd: Home directory is unknown")?,
-        },
         BuiltIn::Cd(path) => {
-            if let Err(e) = change_directory(&PathBuf::from(path)) {
-                writeln!(stderr, "cd: {e}")?;
+            let path = expand_word(shell, path)?;
+            trace(shell, stderr, "cd", &[&path])?;
+            if path == "~" {
+                match std::env::home_dir() {
+                    Some(home) => change_directory(&home)?,
+                    None => {
+                        writeln!(stderr, "cd: Home directory is unknown")?;
+                        return Ok(1);
+                    }
+                }
+            } else if let Err(e) = change_directory(&PathBuf::from(&path)) {
+                // An interactive shell can correct the directory's spelling.
+                let corrected = match shell.shell_options.cdspell && shell.is_interactive {
+                    true => correct_directory_spelling(&path),
+                    false => None,
+                };
+                match corrected {
+                    Some(corrected) => {
+                        writeln!(stdout, "{}", corrected.display())?;
+                        change_directory(&corrected)?;
+                    }
+                    None => {
+                        writeln!(stderr, "cd: {e}")?;
+                        return Ok(1);
+                    }
+                }
             }
         }
         BuiltIn::Exit(code) => {
-            std::process::exit(*code);
+            let code = match code {
+                Some(code) => Some(expand_word(shell, code)?),
+                None => None,
+            };
+            trace(shell, stderr, "exit", code.as_slice())?;
+            let code = match code {
+                Some(code) => match code.parse::<i32>() {
+                    Ok(code) => code,
+                    Err(_) => {
+                        writeln!(stderr, "exit: {code}: numeric argument required")?;
+                        2
+                    }
+                },
+                None => shell.status,
+            };
+            stdout.flush()?;
+            std::process::exit(code);
+        }
+        BuiltIn::Local(args) => {
+            let args = expand_declaration_words(shell, args)?;
+            trace(shell, stderr, "local", &args)?;
+            return declare::local(shell, &args, stderr);
         }
-        BuiltIn::Pwd => match std::env::current_dir() {
-            Ok(current_dir) => {
-                writeln!(stdout, "{}", current_dir.display())?;
+        BuiltIn::Export(args) => {
+            let args = expand_declaration_words(shell, args)?;
+            trace(shell, stderr, "export", &args)?;
+            return declare::export(shell, &args, stdout, stderr);
+        }
+        BuiltIn::Printf(args) => {
+            let args = expand_words(shell, args)?;
+            trace(shell, stderr, "printf", &args)?;
+            return printf::printf(shell, &args, stdout, stderr);
+        }
+        BuiltIn::Read(args) => {
+            let args = expand_words(shell, args)?;
+            trace(shell, stderr, "read", &args)?;
+            return read::read(shell, &args, stdin, stderr);
+        }
+        BuiltIn::Readonly(args) => {
+            let args = expand_declaration_words(shell, args)?;
+            trace(shell, stderr, "readonly", &args)?;
+            return declare::readonly(shell, &args, stdout, stderr);
+        }
+        BuiltIn::Return(code) => {
+            let code = match code {
+                Some(code) => Some(expand_word(shell, code)?),
+                None => None,
+            };
+            trace(shell, stderr, "return", code.as_slice())?;
+            let code = match code {
+                Some(code) => match code.parse::<i32>() {
+                    Ok(code) => code & 0xff,
+                    Err(_) => {
+                        writeln!(stderr, "return: {code}: numeric argument required")?;
+                        2
+                    }
+                },
+                None => shell.status,
+            };
+            if !shell.is_in_function() && shell.source_depth == 0 {
+                writeln!(
+                    stderr,
+                    "return: can only `return' from a function or sourced script"
+                )?;
+                return Ok(1);
             }
-            Err(e) => {
-                writeln!(stderr, "{}", e)?;
+            shell.jump = Some(Jump::Return);
+            return Ok(code);
+        }
+        BuiltIn::Pwd => {
+            trace(shell, stderr, "pwd", &[] as &[&str])?;
+            match std::env::current_dir() {
+                Ok(current_dir) => {
+                    writeln!(stdout, "{}", current_dir.display())?;
+                }
+                Err(e) => {
+                    writeln!(stderr, "{}", e)?;
+                    return Ok(1);
+                }
             }
-        },
-        BuiltIn::Type(command) => match command.as_ref() {
-            "cd" | "echo" | "exit" | "history" | "pwd" | "type" => {
-                writeln!(stdout, "{} is a shell builtin", command)?;
+        }
+        BuiltIn::Set(args) => {
+            let args = expand_words(shell, args)?;
+            trace(shell, stderr, "set", &args)?;
+            return options::set(shell, &args, stdout, stderr);
+        }
+        BuiltIn::Shift(count) => {
+            let count = match count {
+                Some(count) => expand_number(shell, count, "shift")?,
+                None => 1,
+            };
+            trace(shell, stderr, "shift", &[count])?;
+            if count > shell.positional.len() {
+                return Ok(1);
             }
-            _ => match search_for_executable_file(paths, command) {
-                Some(dir_entry) => {
-                    writeln!(stdout, "{} is {}", command, dir_entry.path().display())?;
+            shell.positional.drain(..count);
+        }
+        BuiltIn::Shopt(args) => {
+            let args = expand_words(shell, args)?;
+            trace(shell, stderr, "shopt", &args)?;
+            return options::shopt(shell, &args, stdout, stderr);
+        }
+        BuiltIn::Test(args) => {
+            let args = expand_words(shell, args)?;
+            trace(shell, stderr, "test", &args)?;
+            return condition::test("test", &args, stderr);
+        }
+        BuiltIn::Unset(args) => {
+            let args = expand_words(shell, args)?;
+            trace(shell, stderr, "unset", &args)?;
+            return declare::unset(shell, &args, stderr);
+        }
+        BuiltIn::Type(command) => {
+            let command = expand_word(shell, command)?;
+            trace(shell, stderr, "type", &[&command])?;
+            match command.as_ref() {
+                _ if shell.aliases.contains_key(&command) => {
+                    let value = &shell.aliases[&command];
+                    writeln!(stdout, "{} is aliased to `{}'", command, value)?;
                 }
-                None => {
-                    writeln!(stderr, "{}: not found", command)?;
+                "!" | "[[" | "]]" | "case" | "do" | "done" | "elif" | "else" | "esac" | "fi"
+                | "for" | "function" | "if" | "in" | "then" | "until" | "while" | "{" | "}" => {
+                    writeln!(stdout, "{} is a shell keyword", command)?;
                 }
-            },
-        },
+                _ if SPECIAL_BUILT_INS.contains(&command.as_str()) => {
+                    writeln!(stdout, "{} is a shell builtin", command)?;
+                }
+                _ if shell.functions.contains_key(&command) => {
+                    writeln!(stdout, "{} is a function", command)?;
+                    writeln!(stdout, "{} ()\n{}", command, shell.functions[&command])?;
+                }
+                _ if BUILT_INS.contains(&command.as_str()) => {
+                    writeln!(stdout, "{} is a shell builtin", command)?;
+                }
+                _ => match search_for_executable_file(&shell.paths(), &command) {
+                    Some(dir_entry) => {
+                        writeln!(stdout, "{} is {}", command, dir_entry.path().display())?;
+                    }
+                    None => {
+                        writeln!(stderr, "{}: not found", command)?;
+                        return Ok(1);
+                    }
+                },
+            }
+        }
+        BuiltIn::Unalias(args) => {
+            let args = expand_words(shell, args)?;
+            trace(shell, stderr, "unalias", &args)?;
+            if args.first().is_some_and(|arg| arg == "-a") {
+                shell.aliases.clear();
+                return Ok(0);
+            }
+            if args.is_empty() {
+                writeln!(stderr, "unalias: usage: unalias [-a] name [name ...]")?;
+                return Ok(2);
+            }
+            let mut status = 0;
+            for arg in args {
+                if shell.aliases.remove(&arg).is_none() {
+                    writeln!(stderr, "unalias: {arg}: not found")?;
+                    status = 1;
+                }
+            }
+            return Ok(status);
+        }
         BuiltIn::History(limit) => {
-            print_history(history, stdout, limit)?;
+            let limit = match limit {
+                Some(limit) => Some(expand_number(shell, limit, "history")?),
+                None => None,
+            };
+            trace(shell, stderr, "history", limit.as_slice())?;
+            print_history(history, stdout, &limit)?;
         }
     }
-    Ok(())
+    Ok(0)
 }
 
 fn print_history<H: History, TOut: Write>(
@@ -240,42 +1484,356 @@ fn print_history<H: History, TOut: Write>(
     Ok(())
 }
 
-fn eval_external_command(
+/// Prints the `echo` builtin's arguments, separated by spaces, with a
+/// newline. Leading arguments made only of the option letters are options:
+/// `-n` leaves out the newline, and `-e` or `-E` turn backslash escapes on or
+/// off, which `xpg_echo` turns on by default.
+fn echo<TOut: Write>(shell: &Shell, args: &[String], stdout: &mut TOut) -> anyhow::Result<()> {
+    let mut has_newline = true;
+    let mut is_escaped = shell.shell_options.xpg_echo;
+    let mut count = 0;
+    for arg in args {
+        let Some(flags) = arg.strip_prefix('-') else {
+            break;
+        };
+        if flags.is_empty() || !flags.chars().all(|flag| matches!(flag, 'n' | 'e' | 'E')) {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'n' => has_newline = false,
+                'e' => is_escaped = true,
+                _ => is_escaped = false,
+            }
+        }
+        count += 1;
+    }
+
+    let text = args[count..].join(" ");
+    if !is_escaped {
+        write!(stdout, "{text}")?;
+    } else {
+        // `\c` stops the output, including the newline.
+        let (bytes, is_stopped) = printf::expand_escapes(&text);
+        stdout.write_all(&bytes)?;
+        if is_stopped {
+            return Ok(());
+        }
+    }
+    if has_newline {
+        writeln!(stdout)?;
+    }
+    Ok(())
+}
+
+/// Evaluates an external command and waits for it to finish. Returns its
+/// exit status.
+fn eval_external_command<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &Streams,
     external_command: &ExternalCommand,
-    stdin: Stdio,
-    stdout: Stdio,
-) -> anyhow::Result<std::process::Command> {
-    match &external_command.redirection {
-        Redirection::StdOut {
-            filename,
-            is_append,
-        } => {
-            let stdout = Stdio::from(open_file(filename, *is_append)?);
-            let stderr = Stdio::inherit();
-            let command = eval_external(&external_command.args, stdin, stdout, stderr)?;
-            Ok(command)
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    match start_external_command(shell, history, streams, external_command)? {
+        Started::Child(mut child, streams) => {
+            streams.stdout.collect_child(&mut child)?;
+            wait_for_child(&mut child)
         }
+        Started::Finished(status) => Ok(status),
+    }
+}
 
-        Redirection::StdErr {
-            filename,
-            is_append,
-        } => {
-            let stderr = Stdio::from(open_file(filename, *is_append)?);
-            let command = eval_external(&external_command.args, stdin, stdout, stderr)?;
-            Ok(command)
+/// The result of starting an external command.
+enum Started {
+    /// The command's process is running with the given streams. Any stdout
+    /// piped from the child is left for the caller to read.
+    Child(Child, Streams),
+
+    /// The command was a function call or a built-in, or had no command
+    /// name, and has finished with the given exit status.
+    Finished(i32),
+}
+
+/// Starts an external command. Its name is looked for, once expanded, as a
+/// special built-in, then a function, then a built-in, which are run in the
+/// shell, and finally in the PATH.
+fn start_external_command<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &Streams,
+    external_command: &ExternalCommand,
+) -> anyhow::Result<Started>
+where
+    H: History,
+{
+    shell.line_number = external_command.line;
+    let mut streams = redirect(shell, streams, &external_command.redirections)?;
+    shell.substitution_status = None;
+    let args = expand_words(shell, &external_command.args)?;
+
+    // Without a command, assignments set shell variables, each in turn.
+    if args.is_empty() {
+        for assignment in &external_command.assignments {
+            assign(shell, assignment)?;
+            if shell.options.xtrace {
+                let line = match shell.get_variable(&assignment.name) {
+                    Some(value) if is_scalar_assignment(assignment) => {
+                        format!("{}={}", assignment.name, quote_special(&value))
+                    }
+                    _ => assignment.to_string(),
+                };
+                trace_line(shell, &mut streams.stderr, &line)?;
+            }
+        }
+        return Ok(Started::Finished(shell.substitution_status.unwrap_or(0)));
+    }
+
+    let variables = expand_assignments(shell, &external_command.assignments)?;
+    let function = overriding_function(shell, &args[0]);
+    if function.is_none() {
+        let words = args[1..].iter().map(|arg| Word::quoted(arg)).collect();
+        if let Some(built_in) = BuiltIn::new(&args[0], words)? {
+            let status =
+                eval_built_in_with_variables(shell, history, &mut streams, variables, &built_in)?;
+            return Ok(Started::Finished(status));
+        }
+    }
+
+    let words: Vec<String> = variables
+        .iter()
+        .map(|(name, value)| format!("{name}={}", quote_special(value)))
+        .chain(args.iter().map(|arg| quote_special(arg)))
+        .collect();
+    trace_line(shell, &mut streams.stderr, &words.join(" "))?;
+
+    if let Some(body) = function {
+        let status = with_variables(shell, variables, |shell| {
+            call_function(shell, history, &streams, &body, &args)
+        })?;
+        return Ok(Started::Finished(status));
+    }
+
+    // An interactive shell can change to a directory named as a command.
+    if shell.shell_options.autocd
+        && shell.is_interactive
+        && args.len() == 1
+        && Path::new(&args[0]).is_dir()
+        && search_for_executable_file(&shell.paths(), &args[0]).is_none()
+    {
+        writeln!(streams.stderr, "cd -- {}", args[0])?;
+        let status = match change_directory(&PathBuf::from(&args[0])) {
+            Ok(()) => 0,
+            Err(e) => {
+                writeln!(streams.stderr, "cd: {e}")?;
+                1
+            }
+        };
+        return Ok(Started::Finished(status));
+    }
+
+    // Variables assigned before the command's name override exported ones.
+    let mut environment = shell.exported_variables();
+    environment.extend(variables);
+
+    let stdin = streams.stdin.to_stdio()?;
+    let stdout = streams.stdout.to_stdio()?;
+    let stderr = streams.stderr.to_stdio()?;
+    let mut command = eval_external(&args, &environment, stdin, stdout, stderr)?;
+
+    // Anything the shell wrote must appear before the child's output.
+    io::stdout().flush()?;
+
+    let mut child = spawn_command(&mut command)?;
+    streams.stdin.feed_child(&mut child);
+    Ok(Started::Child(child, streams))
+}
+
+/// Calls a function. The arguments after its name become its positional
+/// parameters while it runs. Returns its exit status.
+fn call_function<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &Streams,
+    body: &Command,
+    args: &[String],
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let mut streams = streams.try_clone()?;
+
+    // Loops in the caller can't be exited from inside the function.
+    let positional = std::mem::replace(&mut shell.positional, args[1..].to_vec());
+    let loop_depth = std::mem::take(&mut shell.loop_depth);
+    shell.push_local_frame();
+
+    let status = eval_command(shell, history, &mut streams, body);
+
+    shell.pop_local_frame();
+    shell.loop_depth = loop_depth;
+    shell.positional = positional;
+    if matches!(shell.jump, Some(Jump::Return)) {
+        shell.jump = None;
+    }
+
+    status
+}
+
+/// Evaluates the `source` or `.` builtin, which runs the commands in a file
+/// in the current shell. Any arguments after the file's name become the
+/// positional parameters while it runs. Returns the exit status of the last
+/// command.
+fn source<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &Streams,
+    name: &str,
+    args: &[Word],
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    let mut streams = streams.try_clone()?;
+    let args = expand_words(shell, args)?;
+    trace(shell, &mut streams.stderr, name, &args)?;
+    let Some(file_name) = args.first() else {
+        writeln!(streams.stderr, "{name}: filename argument required")?;
+        return Ok(2);
+    };
+
+    // A name without a slash is searched for on the PATH first.
+    let path = match file_name.contains('/') {
+        true => None,
+        false => search_for_file(&shell.paths(), file_name),
+    };
+    let path = path.unwrap_or_else(|| PathBuf::from(file_name));
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(e) => {
+            writeln!(streams.stderr, "{name}: {file_name}: {}", error_message(&e))?;
+            return Ok(1);
         }
+    };
+
+    let positional = match args.len() {
+        1 => None,
+        _ => Some(std::mem::replace(&mut shell.positional, args[1..].to_vec())),
+    };
+    shell.source_depth += 1;
+
+    let status = eval_file(shell, history, &mut streams, file_name, file);
+
+    shell.source_depth -= 1;
+    if let Some(positional) = positional {
+        shell.positional = positional;
+    }
+    if matches!(shell.jump, Some(Jump::Return)) {
+        shell.jump = None;
+    }
+
+    status
+}
 
-        Redirection::None => {
-            let stderr = Stdio::inherit();
-            let command = eval_external(&external_command.args, stdin, stdout, stderr)?;
-            Ok(command)
+/// Evaluates the commands in a file, stopping early at a syntax error or a
+/// `return`. Syntax errors are reported with the file's name and the line
+/// they're on. Returns the exit status of the last command.
+fn eval_file<H>(
+    shell: &mut Shell,
+    history: &H,
+    streams: &mut Streams,
+    file_name: &str,
+    file: File,
+) -> anyhow::Result<i32>
+where
+    H: History,
+{
+    shell.status = 0;
+    let mut commands = CommandReader::new(BufReader::new(file));
+    loop {
+        commands.set_extglob(shell.shell_options.extglob);
+        let Some(command_text) = commands.next() else {
+            break;
+        };
+        let (command_text, first_line) = command_text?;
+        if shell.options.verbose {
+            writeln!(streams.stderr, "{command_text}")?;
+        }
+        let command_text = match shell.shell_options.expand_aliases {
+            true => expand_aliases(&shell.aliases, &command_text, shell.shell_options.extglob),
+            false => command_text,
+        };
+        let list = match parse_at_line(&command_text, first_line, shell.shell_options.extglob) {
+            Ok(list) => list,
+            Err(e) => {
+                // Incomplete commands are reported on their last line.
+                let last_line = first_line + command_text.lines().count().max(1) - 1;
+                let line = e
+                    .downcast_ref::<SyntaxError>()
+                    .map_or(last_line, SyntaxError::line);
+                writeln!(streams.stderr, "{file_name}: line {line}: {e}")?;
+                return Ok(2);
+            }
+        };
+        eval_list(shell, history, streams, &list)?;
+        if shell.jump.is_some() {
+            break;
         }
     }
+    Ok(shell.status)
+}
+
+/// Writes a command's expanded words to stderr when `xtrace` is on. Words
+/// are quoted if they have special characters.
+fn trace<T, TErr>(
+    shell: &mut Shell,
+    stderr: &mut TErr,
+    name: &str,
+    args: &[T],
+) -> anyhow::Result<()>
+where
+    T: fmt::Display,
+    TErr: Write,
+{
+    if !shell.options.xtrace {
+        return Ok(());
+    }
+    let mut line = quote_special(name);
+    for arg in args {
+        line.push(' ');
+        line.push_str(&quote_special(&arg.to_string()));
+    }
+    trace_line(shell, stderr, &line)
+}
+
+/// Writes a line of `xtrace` output to stderr when it's on, after the
+/// expansion of `$PS4`.
+fn trace_line<TErr: Write>(shell: &mut Shell, stderr: &mut TErr, line: &str) -> anyhow::Result<()> {
+    if !shell.options.xtrace {
+        return Ok(());
+    }
+    let ps4 = shell
+        .get_variable("PS4")
+        .unwrap_or_else(|| String::from("+ "));
+    let prefix = scan_subscript(&ps4)
+        .and_then(|word| expand_word(shell, &word))
+        .unwrap_or(ps4);
+    writeln!(stderr, "{prefix}{line}")?;
+    Ok(())
+}
+
+/// Determines if an assignment sets a whole variable to a single value,
+/// rather than an array or one of its elements.
+fn is_scalar_assignment(assignment: &Assignment) -> bool {
+    assignment.subscript.is_none() && !matches!(assignment.value.parts[..], [WordPart::Array(_)])
 }
 
 /// Evaluates an external command, e.g. `cd`.
 fn eval_external(
     args: &[String],
+    variables: &[(String, String)],
     stdin: Stdio,
     stdio: Stdio,
     stderr: Stdio,
@@ -285,9 +1843,66 @@ fn eval_external(
     let args = args.iter().skip(1);
     let mut command = std::process::Command::new(command_name);
     command.args(args).stdin(stdin).stdout(stdio).stderr(stderr);
+
+    // The child's environment is exactly the shell's exported variables.
+    command.env_clear();
+    command.envs(variables.iter().map(|(name, value)| (name, value)));
+
     Ok(command)
 }
 
+/// Creates the streams for a command by applying its redirections, in
+/// order, to the streams it would otherwise use.
+fn redirect(
+    shell: &mut Shell,
+    streams: &Streams,
+    redirections: &[Redirection],
+) -> anyhow::Result<Streams> {
+    let mut streams = streams.try_clone()?;
+    for redirection in redirections {
+        redirect_one(shell, &mut streams, redirection)?;
+    }
+    Ok(streams)
+}
+
+/// Applies a redirection to a command's streams.
+fn redirect_one(
+    shell: &mut Shell,
+    streams: &mut Streams,
+    redirection: &Redirection,
+) -> anyhow::Result<()> {
+    match redirection {
+        Redirection::StdIn { filename } => {
+            let filename = expand_word(shell, filename)?;
+            let file =
+                File::open(&filename).map_err(|e| anyhow!("{filename}: {}", error_message(&e)))?;
+            streams.stdin = Input::File(file);
+        }
+
+        Redirection::StdOut {
+            filename,
+            is_append,
+        } => {
+            let filename = expand_word(shell, filename)?;
+            let file = open_file(&filename, *is_append)
+                .map_err(|e| anyhow!("{filename}: {}", error_message(&e)))?;
+            streams.stdout = Output::File(file);
+        }
+
+        Redirection::StdErr {
+            filename,
+            is_append,
+        } => {
+            let filename = expand_word(shell, filename)?;
+            let file = open_file(&filename, *is_append)
+                .map_err(|e| anyhow!("{filename}: {}", error_message(&e)))?;
+            streams.stderr = Output::File(file);
+        }
+    }
+
+    Ok(())
+}
+
 /// Creates a file.
 fn open_file(filename: &str, is_append: bool) -> io::Result<File> {
     let mut open_options = OpenOptions::new();
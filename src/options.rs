@@ -0,0 +1,388 @@
+//! The shell's options, with the `set` builtin, which changes them, e.g.
+//! `set -e` or `set -o errexit`, and the `shopt` builtin, which changes
+//! further options, e.g. `shopt -s nullglob`.
+
+use std::io::Write;
+
+use crate::shell::Shell;
+
+/// Names of the options, for `set -o`, and the letters for them, for `set -e`
+/// and `$-`.
+const OPTIONS: [(&str, char); 6] = [
+    ("errexit", 'e'),
+    ("noexec", 'n'),
+    ("noglob", 'f'),
+    ("nounset", 'u'),
+    ("verbose", 'v'),
+    ("xtrace", 'x'),
+];
+
+/// Names of the options changed with `shopt`.
+const SHELL_OPTIONS: [&str; 11] = [
+    "autocd",
+    "cdspell",
+    "dotglob",
+    "expand_aliases",
+    "extglob",
+    "failglob",
+    "globstar",
+    "histappend",
+    "nocaseglob",
+    "nullglob",
+    "xpg_echo",
+];
+
+/// Options which change how the shell evaluates commands.
+#[derive(Default)]
+pub struct Options {
+    /// Exit when a command fails, unless its exit status is tested.
+    pub errexit: bool,
+
+    /// Read commands without evaluating them, e.g. to check a script's
+    /// syntax. Interactive shells ignore it.
+    pub noexec: bool,
+
+    /// Don't expand pathnames.
+    pub noglob: bool,
+
+    /// Make expanding an unset variable an error.
+    pub nounset: bool,
+
+    /// Write commands to stderr as they're read.
+    pub verbose: bool,
+
+    /// Write commands to stderr after they're expanded.
+    pub xtrace: bool,
+}
+
+impl Options {
+    /// Gets the letters of the options which are on, e.g. for `$-`.
+    pub fn letters(&self) -> String {
+        OPTIONS
+            .iter()
+            .filter(|(name, _)| self.get(name))
+            .map(|(_, letter)| *letter)
+            .collect()
+    }
+
+    /// Determines if an option is on.
+    fn get(&self, name: &str) -> bool {
+        match name {
+            "errexit" => self.errexit,
+            "noexec" => self.noexec,
+            "noglob" => self.noglob,
+            "nounset" => self.nounset,
+            "verbose" => self.verbose,
+            "xtrace" => self.xtrace,
+            _ => false,
+        }
+    }
+
+    /// Turns an option on or off by its name, e.g. for `set -o errexit`.
+    /// Returns `false` if there's no option with the name.
+    pub fn set_name(&mut self, name: &str, is_on: bool) -> bool {
+        self.get_mut(name).map(|option| *option = is_on).is_some()
+    }
+
+    /// Turns an option on or off by its letter, e.g. for `set -e`. Returns
+    /// `false` if there's no option with the letter.
+    pub fn set_letter(&mut self, letter: char, is_on: bool) -> bool {
+        OPTIONS
+            .iter()
+            .find(|(_, l)| *l == letter)
+            .is_some_and(|(name, _)| self.set_name(name, is_on))
+    }
+
+    /// Gets an option by its name, so it can be changed.
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "errexit" => Some(&mut self.errexit),
+            "noexec" => Some(&mut self.noexec),
+            "noglob" => Some(&mut self.noglob),
+            "nounset" => Some(&mut self.nounset),
+            "verbose" => Some(&mut self.verbose),
+            "xtrace" => Some(&mut self.xtrace),
+            _ => None,
+        }
+    }
+}
+
+/// Further options, changed with `shopt`.
+pub struct ShellOptions {
+    /// In an interactive shell, a command which names a directory changes
+    /// to it.
+    pub autocd: bool,
+
+    /// In an interactive shell, `cd` corrects small spelling mistakes in a
+    /// directory's name.
+    pub cdspell: bool,
+
+    /// Pathname expansion matches names starting with `.`.
+    pub dotglob: bool,
+
+    /// Aliases are expanded.
+    pub expand_aliases: bool,
+
+    /// Patterns can use extended operators, e.g. `@(a|b)`.
+    pub extglob: bool,
+
+    /// A pattern which matches no pathnames is an error.
+    pub failglob: bool,
+
+    /// `**` in a pathname pattern matches any number of directories.
+    pub globstar: bool,
+
+    /// History is appended to `$HISTFILE` rather than overwriting it.
+    pub histappend: bool,
+
+    /// Pathname expansion ignores case.
+    pub nocaseglob: bool,
+
+    /// A pattern which matches no pathnames expands to nothing.
+    pub nullglob: bool,
+
+    /// `echo` expands backslash escapes without `-e`, as POSIX says.
+    pub xpg_echo: bool,
+}
+
+impl Default for ShellOptions {
+    fn default() -> ShellOptions {
+        ShellOptions {
+            autocd: false,
+            cdspell: false,
+            dotglob: false,
+            expand_aliases: true,
+            extglob: false,
+            failglob: false,
+            globstar: false,
+            histappend: false,
+            nocaseglob: false,
+            nullglob: false,
+            xpg_echo: false,
+        }
+    }
+}
+
+impl ShellOptions {
+    /// Gets an option by its name, so it can be changed.
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "autocd" => Some(&mut self.autocd),
+            "cdspell" => Some(&mut self.cdspell),
+            "dotglob" => Some(&mut self.dotglob),
+            "expand_aliases" => Some(&mut self.expand_aliases),
+            "extglob" => Some(&mut self.extglob),
+            "failglob" => Some(&mut self.failglob),
+            "globstar" => Some(&mut self.globstar),
+            "histappend" => Some(&mut self.histappend),
+            "nocaseglob" => Some(&mut self.nocaseglob),
+            "nullglob" => Some(&mut self.nullglob),
+            "xpg_echo" => Some(&mut self.xpg_echo),
+            _ => None,
+        }
+    }
+}
+
+/// Evaluates the `set` builtin. Options start with `-` to turn them on or
+/// `+` to turn them off, and the arguments after them, or after `--`, become
+/// the positional parameters. Without arguments, the shell's variables are
+/// printed.
+pub fn set<TOut: Write, TErr: Write>(
+    shell: &mut Shell,
+    args: &[String],
+    stdout: &mut TOut,
+    stderr: &mut TErr,
+) -> anyhow::Result<i32> {
+    if args.is_empty() {
+        for (name, variable) in shell.variables() {
+            if let Some(value) = variable.quoted_value() {
+                writeln!(stdout, "{name}={value}")?;
+            }
+        }
+        return Ok(0);
+    }
+
+    let mut i = 0;
+    let mut has_operands = false;
+    while let Some(arg) = args.get(i) {
+        i += 1;
+        if arg == "--" {
+            has_operands = true;
+            break;
+        }
+
+        // A lone `-` turns off `xtrace` and `verbose`, as it did in older
+        // shells.
+        if arg == "-" {
+            shell.options.xtrace = false;
+            shell.options.verbose = false;
+            break;
+        }
+
+        let (is_on, letters) = match (arg.strip_prefix('-'), arg.strip_prefix('+')) {
+            (Some(letters), _) if !letters.is_empty() => (true, letters),
+            (_, Some(letters)) if !letters.is_empty() => (false, letters),
+            _ => {
+                i -= 1;
+                break;
+            }
+        };
+        for letter in letters.chars() {
+            if letter == 'o' {
+                let Some(name) = args.get(i) else {
+                    print_options(shell, is_on, stdout)?;
+                    continue;
+                };
+                i += 1;
+                if !shell.options.set_name(name, is_on) {
+                    writeln!(stderr, "set: {name}: invalid option name")?;
+                    return Ok(2);
+                }
+                continue;
+            }
+
+            if !shell.options.set_letter(letter, is_on) {
+                let sign = if is_on { '-' } else { '+' };
+                writeln!(stderr, "set: {sign}{letter}: invalid option")?;
+                return Ok(2);
+            }
+        }
+    }
+
+    if has_operands || i < args.len() {
+        shell.positional = args[i..].to_vec();
+    }
+    Ok(0)
+}
+
+/// Prints whether each option is on, for `set -o`, or the commands which
+/// restore the options as they are, for `set +o`.
+fn print_options<TOut: Write>(shell: &Shell, is_on: bool, stdout: &mut TOut) -> anyhow::Result<()> {
+    for (name, _) in OPTIONS {
+        let value = shell.options.get(name);
+        if is_on {
+            let value = if value { "on" } else { "off" };
+            writeln!(stdout, "{name:<15}\t{value}")?;
+        } else {
+            let sign = if value { '-' } else { '+' };
+            writeln!(stdout, "set {sign}o {name}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Evaluates the `shopt` builtin. `-s` turns the named options on and `-u`
+/// turns them off. Otherwise the named options, or all of them, are printed,
+/// in a form which can be read back in with `-p`. With `-q`, nothing is
+/// printed and the exit status tells if the named options are all on. `-o`
+/// uses the options of `set -o` instead.
+pub fn shopt<TOut: Write, TErr: Write>(
+    shell: &mut Shell,
+    args: &[String],
+    stdout: &mut TOut,
+    stderr: &mut TErr,
+) -> anyhow::Result<i32> {
+    let mut change = None;
+    let mut is_printable = false;
+    let mut is_quiet = false;
+    let mut is_set_option = false;
+
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        if arg == "--" {
+            i += 1;
+            break;
+        }
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            break;
+        };
+        for flag in flags.chars() {
+            match flag {
+                's' | 'u' if change == Some(flag == 'u') => {
+                    writeln!(
+                        stderr,
+                        "shopt: cannot set and unset shell options simultaneously"
+                    )?;
+                    return Ok(1);
+                }
+                's' => change = Some(true),
+                'u' => change = Some(false),
+                'p' => is_printable = true,
+                'q' => is_quiet = true,
+                'o' => is_set_option = true,
+                _ => {
+                    writeln!(stderr, "shopt: -{flag}: invalid option")?;
+                    writeln!(stderr, "shopt: usage: shopt [-pqsu] [-o] [optname ...]")?;
+                    return Ok(2);
+                }
+            }
+        }
+        i += 1;
+    }
+    let names = &args[i..];
+
+    if let (Some(value), false) = (change, names.is_empty()) {
+        let mut status = 0;
+        for name in names {
+            match option_mut(shell, is_set_option, name) {
+                Some(option) => *option = value,
+                None => {
+                    writeln!(stderr, "shopt: {name}: invalid shell option name")?;
+                    status = 1;
+                }
+            }
+        }
+        return Ok(status);
+    }
+
+    // Without names, all the options are printed, or with `-s` or `-u` those
+    // which are on or off, and the exit status doesn't depend on them.
+    let is_all = names.is_empty();
+    let names: Vec<String> = match (is_all, is_set_option) {
+        (true, true) => OPTIONS.iter().map(|(name, _)| name.to_string()).collect(),
+        (true, false) => SHELL_OPTIONS.iter().map(|name| name.to_string()).collect(),
+        (false, _) => names.to_vec(),
+    };
+
+    let mut status = 0;
+    for name in &names {
+        let Some(value) = option_mut(shell, is_set_option, name).map(|option| *option) else {
+            writeln!(stderr, "shopt: {name}: invalid shell option name")?;
+            status = 1;
+            continue;
+        };
+        if change.is_some_and(|change| change != value) {
+            continue;
+        }
+        if !value && !is_all {
+            status = 1;
+        }
+        if is_quiet {
+            continue;
+        }
+        match (is_printable, is_set_option) {
+            (true, true) => {
+                let sign = if value { '-' } else { '+' };
+                writeln!(stdout, "set {sign}o {name}")?;
+            }
+            (true, false) => {
+                let flag = if value { 's' } else { 'u' };
+                writeln!(stdout, "shopt -{flag} {name}")?;
+            }
+            (false, _) => {
+                let value = if value { "on" } else { "off" };
+                writeln!(stdout, "{name:<15}\t{value}")?;
+            }
+        }
+    }
+    Ok(status)
+}
+
+/// Gets an option of `shopt`, or of `set -o`, by its name, so it can be
+/// changed.
+fn option_mut<'a>(shell: &'a mut Shell, is_set_option: bool, name: &str) -> Option<&'a mut bool> {
+    match is_set_option {
+        true => shell.options.get_mut(name),
+        false => shell.shell_options.get_mut(name),
+    }
+}
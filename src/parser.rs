@@ -1,68 +1,679 @@
 //! A command parser.
 
+use std::rc::Rc;
+
 use anyhow::anyhow;
 
 use crate::ast::*;
-use crate::scanner::{Scanner, TokenTag};
+use crate::condition::{is_binary_operator, is_unary_operator};
+use crate::error::{in_construct, is_incomplete, IncompleteError, SyntaxError};
+use crate::scanner::{is_name, Scanner, TokenTag};
 use parser_state::ParserState;
 
 type PS<'a> = ParserState<Scanner<'a>>;
 
-/// Parses a given command text. Returns an array of commands which represents
-/// a pipeline.
-pub fn parse(command_text: &str) -> anyhow::Result<Vec<Command>> {
-    let scanner = Scanner::new(command_text);
+/// Parses a given command text. Returns the list of commands it contains.
+/// Extended pattern groups, e.g. `@(a|b)`, are parsed if `is_extglob`.
+pub fn parse(command_text: &str, is_extglob: bool) -> anyhow::Result<List> {
+    parse_at_line(command_text, 1, is_extglob)
+}
+
+/// Parses a command text which starts on the given line, so commands know
+/// their line numbers. Errors other than incomplete command text are
+/// `SyntaxError`s which know the line they're on.
+pub fn parse_at_line(command_text: &str, line: usize, is_extglob: bool) -> anyhow::Result<List> {
+    let scanner = Scanner::at_line(command_text, line).with_extglob(is_extglob);
+    let mut state = ParserState::new(scanner).map_err(|e| syntax_error(e, line))?;
+    let result = list(&mut state).and_then(|list| match state.current.tag {
+        TokenTag::EndOfCommand => Ok(list),
+        _ => Err(unexpected_token(&state)),
+    });
+    result.map_err(|e| syntax_error(e, state.current.line))
+}
+
+/// Parses the commands of a `$(...)` command substitution, which start the
+/// given text and end at a `)`. Returns the length of their text.
+pub fn parse_command_substitution(
+    text: &str,
+    line: usize,
+    is_extglob: bool,
+) -> anyhow::Result<usize> {
+    let scanner = Scanner::at_line(text, line).with_extglob(is_extglob);
     let mut state = ParserState::new(scanner)?;
+    list(&mut state).map_err(|e| in_construct(e, ")"))?;
     match state.current.tag {
-        TokenTag::Word => {
-            let pipeline = pipeline(&mut state)?;
-            Ok(pipeline)
+        TokenTag::RightParen => Ok(state.current.span.start),
+        TokenTag::EndOfCommand => {
+            Err(IncompleteError::new("unclosed command substitution", ")").into())
+        }
+        _ => Err(unexpected_token(&state)),
+    }
+}
+
+/// Makes a parse error a `SyntaxError` on the given line, unless it's for
+/// incomplete command text. Text which ends outside any open construct, e.g.
+/// `echo >`, is a syntax error rather than incomplete.
+fn syntax_error(e: anyhow::Error, line: usize) -> anyhow::Error {
+    match is_incomplete(&e) {
+        true => e,
+        false => SyntaxError::new(e.to_string(), line).into(),
+    }
+}
+
+/// Parses a list of and-or lists separated by `;` or newlines. The list ends
+/// at the end of the command text or at a token which can't start a command,
+/// e.g. `done`.
+fn list(state: &mut PS) -> anyhow::Result<List> {
+    let mut list = Vec::new();
+
+    skip_newlines(state)?;
+    while is_command_start(state) {
+        let mut and_or = and_or(state)?;
+        and_or.is_async = state.matches(TokenTag::Ampersand)?;
+        let is_separated = and_or.is_async
+            || state.matches(TokenTag::Semicolon)?
+            || state.matches(TokenTag::Newline)?;
+        list.push(and_or);
+        if !is_separated {
+            break;
         }
-        TokenTag::EndOfCommand => Ok(Vec::new()),
-        tag => Err(anyhow!("unexpected token `{:?}`", tag)),
+        skip_newlines(state)?;
     }
+
+    Ok(list)
 }
 
-/// Parses a pipeline of commands. Returns a vector of all commands in the
-/// pipeline in order.
-fn pipeline(state: &mut PS) -> anyhow::Result<Vec<Command>> {
+/// Parses the body of a compound command, which must not be empty.
+fn compound_list(state: &mut PS) -> anyhow::Result<List> {
+    let list = list(state)?;
+    if list.is_empty() {
+        return Err(unexpected_token(state));
+    }
+    Ok(list)
+}
+
+/// Parses pipelines joined by `&&` and `||`.
+fn and_or(state: &mut PS) -> anyhow::Result<AndOr> {
+    let first = pipeline(state)?;
+    let mut rest = Vec::new();
+
+    loop {
+        let connector = if state.matches(TokenTag::And)? {
+            Connector::And
+        } else if state.matches(TokenTag::Or)? {
+            Connector::Or
+        } else {
+            break;
+        };
+        // A trailing `&&` or `||` continues on the next line.
+        skip_newlines(state)?;
+        let pipeline = pipeline(state).map_err(|e| in_construct(e, ""))?;
+        rest.push((connector, pipeline));
+    }
+
+    Ok(AndOr {
+        first,
+        rest,
+        is_async: false,
+    })
+}
+
+/// Parses a pipeline of commands, which may be negated with `!`.
+fn pipeline(state: &mut PS) -> anyhow::Result<Pipeline> {
+    let is_negated = is_keyword(state, "!");
+    if is_negated {
+        state.advance()?;
+    }
+
     let mut commands = Vec::new();
 
+    // A trailing `|` continues on the next line.
     let mut parse_another_command = true;
     while parse_another_command {
-        let command = command(state)?;
+        let command = match commands.is_empty() {
+            true => command(state)?,
+            false => command(state).map_err(|e| in_construct(e, ""))?,
+        };
         commands.push(command);
         parse_another_command = state.matches(TokenTag::Pipe)?;
+        if parse_another_command {
+            skip_newlines(state)?;
+        }
     }
 
-    Ok(commands)
+    Ok(Pipeline {
+        is_negated,
+        commands,
+    })
 }
 
 fn command(state: &mut PS) -> anyhow::Result<Command> {
-    assert!(state.current.tag == TokenTag::Word);
+    if !is_command_start(state) {
+        return Err(unexpected_token(state));
+    }
+
+    // Compound commands which end before their closing keyword are
+    // incomplete, rather than syntax errors.
+    let closing = |closer| move |e| in_construct(e, closer);
+    match state.current.tag {
+        TokenTag::Arithmetic => return arithmetic_command(state),
+        TokenTag::FunctionName => return function_definition(state),
+        TokenTag::LeftParen => return subshell(state).map_err(closing(")")),
+        _ => {}
+    }
+
+    match state.current.lexeme.as_ref() {
+        "{" => return brace_group(state).map_err(closing("}")),
+        "[[" => return conditional_command(state).map_err(closing("]]")),
+        "case" => return case_command(state).map_err(closing("esac")),
+        "for" => return for_command(state).map_err(closing("done")),
+        "function" => return function_definition(state),
+        "if" => return if_command(state).map_err(closing("fi")),
+        "while" | "until" => return while_command(state).map_err(closing("done")),
+        _ => {}
+    }
+
+    let line = state.current.line;
+    let assignments = assignments(state)?;
+    let built_in = match state.current.tag {
+        TokenTag::Word => built_in(state)?,
+        _ => None,
+    };
 
-    let command = if let Some(built_in) = built_in(state)? {
-        let redirection = redirection(state)?;
+    let command = if let Some(built_in) = built_in {
+        let redirections = redirections(state)?;
         let built_in_command = BuiltInCommand {
+            assignments,
             built_in,
-            redirection,
+            redirections,
+            line,
         };
         Command::BuiltIn(built_in_command)
     } else {
-        let args = collect_integer_word(state)?;
-        let redirection = redirection(state)?;
-        let external_command = ExternalCommand { args, redirection };
+        let args = collect_words(state)?;
+        let redirections = redirections(state)?;
+        let external_command = ExternalCommand {
+            assignments,
+            args,
+            redirections,
+            line,
+        };
         Command::External(external_command)
     };
 
     Ok(command)
 }
 
-fn redirection(state: &mut PS) -> anyhow::Result<Redirection> {
+/// Parses any assignment words before a simple command's name.
+fn assignments(state: &mut PS) -> anyhow::Result<Vec<Assignment>> {
+    let mut assignments = Vec::new();
+    while state.current.tag == TokenTag::Word {
+        let Some(assignment) = state.current.word.as_ref().and_then(assignment) else {
+            break;
+        };
+        assignments.push(assignment);
+        state.advance()?;
+    }
+    Ok(assignments)
+}
+
+/// Splits an assignment word, e.g. `name=value` or `name[1]=value`, into
+/// its name, subscript and value. Returns `None` if the word isn't an
+/// assignment, which needs an unquoted name before the `=`.
+pub fn assignment(word: &Word) -> Option<Assignment> {
+    let Some(WordPart::Literal(first)) = word.parts.first() else {
+        return None;
+    };
+    let name_len = first
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(first.len());
+    let (name, rest) = first.split_at(name_len);
+    if !is_name(name) {
+        return None;
+    }
+
+    if let Some(value) = rest.strip_prefix('=') {
+        return Some(Assignment {
+            name: name.to_string(),
+            subscript: None,
+            value: join_parts(value, &word.parts[1..]),
+        });
+    }
+
+    let (subscript, value) = split_subscript(rest.strip_prefix('[')?, &word.parts)?;
+    Some(Assignment {
+        name: name.to_string(),
+        subscript: Some(subscript),
+        value,
+    })
+}
+
+/// Splits an element of an array assignment with a subscript, e.g.
+/// `[1]=value`, into its subscript and value.
+pub fn array_element(word: &Word) -> Option<(Word, Word)> {
+    let Some(WordPart::Literal(first)) = word.parts.first() else {
+        return None;
+    };
+    split_subscript(first.strip_prefix('[')?, &word.parts)
+}
+
+/// Splits a word's parts at the first `]=` into a subscript and a value.
+/// `rest` is the text of the first part after the opening `[`. The `]=` may
+/// be in a later part, e.g. in `arr[$i]=value`.
+fn split_subscript(rest: &str, parts: &[WordPart]) -> Option<(Word, Word)> {
+    let mut subscript = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        let text = match part {
+            WordPart::Literal(_) if i == 0 => rest,
+            WordPart::Literal(text) => text,
+            part => {
+                subscript.push(part.clone());
+                continue;
+            }
+        };
+        match text.split_once("]=") {
+            Some((inside, value)) => {
+                if !inside.is_empty() {
+                    subscript.push(WordPart::Literal(inside.to_string()));
+                }
+                let value = join_parts(value, &parts[i + 1..]);
+                return Some((Word { parts: subscript }, value));
+            }
+            None if !text.is_empty() => subscript.push(WordPart::Literal(text.to_string())),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Makes a word from literal text followed by other parts.
+fn join_parts(text: &str, parts: &[WordPart]) -> Word {
+    let mut joined = Vec::new();
+    if !text.is_empty() {
+        joined.push(WordPart::Literal(text.to_string()));
+    }
+    joined.extend(parts.iter().cloned());
+    Word { parts: joined }
+}
+
+/// Parses an arithmetic command, e.g. `(( i++ ))`.
+fn arithmetic_command(state: &mut PS) -> anyhow::Result<Command> {
+    let expression = state.expect_lexeme(TokenTag::Arithmetic)?;
+    let redirections = redirections(state)?;
+    Ok(Command::Arithmetic(ArithmeticCommand {
+        expression,
+        redirections,
+    }))
+}
+
+/// Parses a for loop over words or a C-style arithmetic for loop.
+fn for_command(state: &mut PS) -> anyhow::Result<Command> {
+    expect_keyword(state, "for")?;
+
+    if state.current.tag == TokenTag::Arithmetic {
+        return arithmetic_for_command(state);
+    }
+
+    if state.current.tag != TokenTag::Word {
+        return Err(unexpected_token(state));
+    }
+    let name = state.expect_lexeme(TokenTag::Word)?;
+    if !is_name(&name) {
+        Err(anyhow!("`{name}': not a valid identifier"))?
+    }
+
+    skip_newlines(state)?;
+    let words = if is_keyword(state, "in") {
+        state.advance()?;
+        let words = collect_words(state)?;
+        if !(state.matches(TokenTag::Semicolon)? || state.matches(TokenTag::Newline)?) {
+            return Err(unexpected_token(state));
+        }
+        Some(words)
+    } else {
+        state.matches(TokenTag::Semicolon)?;
+        None
+    };
+    skip_newlines(state)?;
+
+    let body = do_group(state)?;
+    let redirections = redirections(state)?;
+
+    Ok(Command::For(ForCommand {
+        name,
+        words,
+        body,
+        redirections,
+    }))
+}
+
+/// Parses the rest of a C-style for loop after `for`.
+fn arithmetic_for_command(state: &mut PS) -> anyhow::Result<Command> {
+    let expressions = state.expect_lexeme(TokenTag::Arithmetic)?;
+    let [init, condition, update] = expressions
+        .split(';')
+        .map(String::from)
+        .collect::<Vec<String>>()
+        .try_into()
+        .map_err(|_| anyhow!("(({expressions})): expected three expressions"))?;
+
+    state.matches(TokenTag::Semicolon)?;
+    skip_newlines(state)?;
+
+    let body = do_group(state)?;
+    let redirections = redirections(state)?;
+
+    Ok(Command::ArithmeticFor(ArithmeticForCommand {
+        init,
+        condition,
+        update,
+        body,
+        redirections,
+    }))
+}
+
+/// Parses a case command.
+fn case_command(state: &mut PS) -> anyhow::Result<Command> {
+    expect_keyword(state, "case")?;
+    let subject = word(state)?;
+    skip_newlines(state)?;
+    expect_keyword(state, "in")?;
+    skip_newlines(state)?;
+
+    let mut items = Vec::new();
+    while !is_keyword(state, "esac") {
+        state.matches(TokenTag::LeftParen)?;
+        let mut patterns = vec![word(state)?];
+        while state.matches(TokenTag::Pipe)? {
+            patterns.push(word(state)?);
+        }
+        expect_token(state, TokenTag::RightParen)?;
+
+        let body = list(state)?;
+
+        let terminator = match state.current.tag {
+            TokenTag::DoubleSemicolon => CaseTerminator::Break,
+            TokenTag::CaseFallThrough => CaseTerminator::FallThrough,
+            TokenTag::CaseContinue => CaseTerminator::Continue,
+            _ if is_keyword(state, "esac") => CaseTerminator::Break,
+            _ => return Err(unexpected_token(state)),
+        };
+        if !is_keyword(state, "esac") {
+            state.advance()?;
+        }
+        skip_newlines(state)?;
+
+        items.push(CaseItem {
+            patterns,
+            body,
+            terminator,
+        });
+    }
+    state.advance()?;
+
+    let redirections = redirections(state)?;
+
+    Ok(Command::Case(CaseCommand {
+        word: subject,
+        items,
+        redirections,
+    }))
+}
+
+/// Parses a conditional command, e.g. `[[ -f $file ]]`. Its words are parsed
+/// differently than a simple command's: `<` and `>` compare strings rather
+/// than being redirections, and `&&`, `||`, `!` and parentheses combine
+/// tests.
+fn conditional_command(state: &mut PS) -> anyhow::Result<Command> {
+    expect_keyword(state, "[[")?;
+    let expression = conditional_or(state)?;
+    skip_newlines(state)?;
+    expect_keyword(state, "]]")?;
+    let redirections = redirections(state)?;
+    Ok(Command::Conditional(ConditionalCommand {
+        expression,
+        redirections,
+    }))
+}
+
+/// Parses conditional expressions joined by `||`.
+fn conditional_or(state: &mut PS) -> anyhow::Result<ConditionalExpression> {
+    let mut expression = conditional_and(state)?;
+    while state.matches(TokenTag::Or)? {
+        let right = conditional_and(state)?;
+        expression = ConditionalExpression::Or(Box::new(expression), Box::new(right));
+    }
+    Ok(expression)
+}
+
+/// Parses conditional expressions joined by `&&`.
+fn conditional_and(state: &mut PS) -> anyhow::Result<ConditionalExpression> {
+    let mut expression = conditional_not(state)?;
+    while state.matches(TokenTag::And)? {
+        let right = conditional_not(state)?;
+        expression = ConditionalExpression::And(Box::new(expression), Box::new(right));
+    }
+    Ok(expression)
+}
+
+/// Parses a conditional expression which may be negated by `!`.
+fn conditional_not(state: &mut PS) -> anyhow::Result<ConditionalExpression> {
+    skip_newlines(state)?;
+    if is_keyword(state, "!") {
+        state.advance()?;
+        let expression = conditional_not(state)?;
+        return Ok(ConditionalExpression::Not(Box::new(expression)));
+    }
+    conditional_primary(state)
+}
+
+/// Parses a test, a word or a conditional expression in parentheses.
+fn conditional_primary(state: &mut PS) -> anyhow::Result<ConditionalExpression> {
+    if state.matches(TokenTag::LeftParen)? {
+        let expression = conditional_or(state)?;
+        skip_newlines(state)?;
+        expect_token(state, TokenTag::RightParen)?;
+        return Ok(ConditionalExpression::Group(Box::new(expression)));
+    }
+
+    if is_unary_operator(&state.current.lexeme) && state.current.tag == TokenTag::Word {
+        let operator = state.advance_keep_current()?.lexeme;
+        let operand = conditional_word(state)?;
+        return Ok(ConditionalExpression::Unary(operator, operand));
+    }
+
+    let left = conditional_word(state)?;
+    let operator = match state.current.tag {
+        TokenTag::Word | TokenTag::RedirectIn | TokenTag::RedirectOut => {
+            state.current.lexeme.clone()
+        }
+        _ => String::new(),
+    };
+    if !(is_binary_operator(&operator) || operator == "=~") {
+        return Ok(ConditionalExpression::Word(left));
+    }
+    state.advance()?;
+    let right = match operator.as_ref() {
+        "=~" => regular_expression(state)?,
+        _ => conditional_word(state)?,
+    };
+    Ok(ConditionalExpression::Binary(left, operator, right))
+}
+
+/// Parses an operand in a conditional expression, which can't be `]]`.
+fn conditional_word(state: &mut PS) -> anyhow::Result<Word> {
+    if is_keyword(state, "]]") {
+        return Err(unexpected_token(state));
+    }
+    word(state)
+}
+
+/// Parses the regular expression after `=~`. It may contain parentheses and
+/// `|` without quoting them, and blanks inside parentheses, so the tokens it
+/// was scanned into are joined back together.
+fn regular_expression(state: &mut PS) -> anyhow::Result<Word> {
+    let mut parts = Vec::new();
+    let mut end = None;
+    let mut depth = 0;
+    loop {
+        let is_first = end.is_none();
+        let is_joined = end == Some(state.current.span.start);
+        let is_part = match state.current.tag {
+            TokenTag::Word => !is_keyword(state, "]]"),
+            TokenTag::LeftParen | TokenTag::Pipe | TokenTag::Or => true,
+            TokenTag::RightParen => depth > 0,
+            _ => false,
+        };
+        if !is_part && (is_first || depth > 0) {
+            return Err(unexpected_token(state));
+        }
+        if !is_part || !(is_first || is_joined || depth > 0) {
+            break;
+        }
+
+        if !is_first && !is_joined {
+            parts.push(WordPart::Literal(String::from(" ")));
+        }
+        match state.current.tag {
+            TokenTag::LeftParen => depth += 1,
+            TokenTag::RightParen => depth -= 1,
+            _ => {}
+        }
+        end = Some(state.current.span.end);
+        let token = state.advance_keep_current()?;
+        match token.word {
+            Some(word) => parts.extend(word.parts),
+            None => parts.push(WordPart::Literal(token.lexeme)),
+        }
+    }
+    Ok(Word { parts })
+}
+
+/// Parses a function definition, e.g. `name() { ...; }` or
+/// `function name { ...; }`.
+fn function_definition(state: &mut PS) -> anyhow::Result<Command> {
+    let name = if is_keyword(state, "function") {
+        state.advance()?;
+        match state.current.tag {
+            TokenTag::Word | TokenTag::FunctionName => state.advance_keep_current()?.lexeme,
+            _ => return Err(unexpected_token(state)),
+        }
+    } else {
+        state.expect_lexeme(TokenTag::FunctionName)?
+    };
+
+    if is_keyword_name(&name) {
+        Err(anyhow!("`{name}': not a valid identifier"))?
+    }
+
+    // The body can be on a later line.
+    skip_newlines(state)?;
+    if !is_compound_start(state) {
+        return Err(in_construct(unexpected_token(state), ""));
+    }
+    let body = command(state)?;
+
+    Ok(Command::FunctionDefinition(FunctionDefinition {
+        name,
+        body: Rc::new(body),
+    }))
+}
+
+/// Parses a brace group, e.g. `{ ...; }`.
+fn brace_group(state: &mut PS) -> anyhow::Result<Command> {
+    expect_keyword(state, "{")?;
+    let body = compound_list(state)?;
+    expect_keyword(state, "}")?;
+    let redirections = redirections(state)?;
+    Ok(Command::Group(GroupCommand { body, redirections }))
+}
+
+/// Parses a subshell, e.g. `( ... )`.
+fn subshell(state: &mut PS) -> anyhow::Result<Command> {
+    state.expect(TokenTag::LeftParen)?;
+    let body = compound_list(state)?;
+    expect_token(state, TokenTag::RightParen)?;
+    let redirections = redirections(state)?;
+    Ok(Command::Subshell(SubshellCommand { body, redirections }))
+}
+
+/// Parses an if command.
+fn if_command(state: &mut PS) -> anyhow::Result<Command> {
+    expect_keyword(state, "if")?;
+
+    let mut branches = Vec::new();
+    let mut else_body = None;
+    loop {
+        let condition = compound_list(state)?;
+        expect_keyword(state, "then")?;
+        let body = compound_list(state)?;
+        branches.push((condition, body));
+
+        if is_keyword(state, "elif") {
+            state.advance()?;
+        } else if is_keyword(state, "else") {
+            state.advance()?;
+            else_body = Some(compound_list(state)?);
+            expect_keyword(state, "fi")?;
+            break;
+        } else {
+            expect_keyword(state, "fi")?;
+            break;
+        }
+    }
+
+    let redirections = redirections(state)?;
+
+    Ok(Command::If(IfCommand {
+        branches,
+        else_body,
+        redirections,
+    }))
+}
+
+/// Parses a while or until loop.
+fn while_command(state: &mut PS) -> anyhow::Result<Command> {
+    let is_until = is_keyword(state, "until");
+    state.advance()?;
+
+    let condition = compound_list(state)?;
+    let body = do_group(state)?;
+    let redirections = redirections(state)?;
+
+    Ok(Command::While(WhileCommand {
+        condition,
+        body,
+        is_until,
+        redirections,
+    }))
+}
+
+/// Parses a loop body between `do` and `done`.
+fn do_group(state: &mut PS) -> anyhow::Result<List> {
+    expect_keyword(state, "do")?;
+    let body = compound_list(state)?;
+    expect_keyword(state, "done")?;
+    Ok(body)
+}
+
+/// Parses any number of redirections, which are applied in order.
+fn redirections(state: &mut PS) -> anyhow::Result<Vec<Redirection>> {
+    let mut redirections = Vec::new();
+    while let Some(redirection) = redirection(state)? {
+        redirections.push(redirection);
+    }
+    Ok(redirections)
+}
+
+fn redirection(state: &mut PS) -> anyhow::Result<Option<Redirection>> {
     use Redirection::*;
     use TokenTag::*;
 
     let redirection = match state.current.tag {
+        RedirectIn => StdIn {
+            filename: redirection_filename(state)?,
+        },
+
         RedirectOut | RedirectOutWithFileDescriptor(1) => StdOut {
             filename: redirection_filename(state)?,
             is_append: false,
@@ -85,110 +696,146 @@ fn redirection(state: &mut PS) -> anyhow::Result<Redirection> {
         RedirectOutWithFileDescriptor(x) => Err(anyhow!("unrecognized file descriptor {x}"))?,
         RedirectOutAppendWithFileDescriptor(x) => Err(anyhow!("unrecognized file descriptor {x}"))?,
 
-        _ => None,
+        _ => return Ok(None),
     };
 
-    Ok(redirection)
+    Ok(Some(redirection))
 }
 
-fn redirection_filename(state: &mut PS) -> anyhow::Result<String> {
+fn redirection_filename(state: &mut PS) -> anyhow::Result<Word> {
     // Advance past the redirection operator.
     state.advance()?;
 
-    let filename = state.expect_lexeme(TokenTag::Word)?;
-    Ok(filename)
+    // A missing filename is an error even inside an open construct.
+    if state.current.tag == TokenTag::EndOfCommand {
+        return Err(anyhow!("syntax error near unexpected token `newline`"));
+    }
+    word(state)
 }
 
+/// Parses a built-in command with its arguments, if the command's name is
+/// a built-in's.
 fn built_in(state: &mut PS) -> anyhow::Result<Option<BuiltIn>> {
     assert!(state.current.tag == TokenTag::Word);
-    let built_in = match state.current.lexeme.as_ref() {
-        "cd" => cd(state)?,
-        "echo" => echo(state)?,
-        "exit" => exit(state)?,
-        "history" => history(state)?,
-        "pwd" => pwd(state)?,
-        "type" => type_builtin(state)?,
-        _ => return Ok(None),
-    };
-    Ok(Some(built_in))
-}
-
-/// Parses a cd command.
-fn cd(state: &mut PS) -> anyhow::Result<BuiltIn> {
-    assert!(state.current.tag == TokenTag::Word);
-    assert!(state.current.lexeme == "cd");
+    let name = state.current.lexeme.clone();
+    if !BUILT_INS.contains(&name.as_str()) {
+        return Ok(None);
+    }
     state.advance()?;
-    let path = state.expect_lexeme(TokenTag::Word)?;
-    Ok(BuiltIn::Cd(path))
+    let args = collect_words(state)?;
+    BuiltIn::new(&name, args)
 }
 
-/// Parses an echo commmand.
-fn echo(state: &mut PS) -> anyhow::Result<BuiltIn> {
-    assert!(state.current.tag == TokenTag::Word);
-    assert!(state.current.lexeme == "echo");
-    state.advance()?;
-    let args = collect_integer_word(state)?;
-    Ok(BuiltIn::Echo(args))
+/// Parses a word token.
+fn word(state: &mut PS) -> anyhow::Result<Word> {
+    if state.current.tag != TokenTag::Word {
+        return Err(unexpected_token(state));
+    }
+    let token = state.advance_keep_current()?;
+    Ok(token.word.unwrap_or_default())
 }
 
-/// Parses an exit command.
-fn exit(state: &mut PS) -> anyhow::Result<BuiltIn> {
-    assert!(state.current.tag == TokenTag::Word);
-    assert!(state.current.lexeme == "exit");
-
-    state.advance()?;
+/// Collects word tokens into a vector.
+fn collect_words(state: &mut PS) -> anyhow::Result<Vec<Word>> {
+    let mut items = Vec::new();
+    while state.current.tag == TokenTag::Word {
+        items.push(word(state)?);
+    }
+    Ok(items)
+}
 
-    let status = match state.current.tag {
-        TokenTag::Integer(status) => {
-            state.advance()?;
-            status
-        }
-        _ => 0,
-    };
+/// Advances past any newline tokens.
+fn skip_newlines(state: &mut PS) -> anyhow::Result<()> {
+    while state.matches(TokenTag::Newline)? {}
+    Ok(())
+}
 
-    Ok(BuiltIn::Exit(status as i32))
+/// Determines if the current token is an unquoted reserved word.
+fn is_keyword(state: &PS, keyword: &str) -> bool {
+    state.current.tag == TokenTag::Word && state.current.lexeme == keyword
 }
 
-/// Parses a history command.
-fn history(state: &mut PS) -> anyhow::Result<BuiltIn> {
-    assert!(state.current.tag == TokenTag::Word);
-    assert!(state.current.lexeme == "history");
-    state.advance()?;
+/// Advances past a reserved word, or fails if it isn't the current token.
+fn expect_keyword(state: &mut PS, keyword: &str) -> anyhow::Result<()> {
+    if is_keyword(state, keyword) {
+        state.advance()?;
+        Ok(())
+    } else {
+        Err(unexpected_token(state))
+    }
+}
 
-    let limit = match state.current.tag {
-        TokenTag::Integer(limit) => {
-            state.advance()?;
-            Some(limit as usize)
-        }
-        _ => None,
-    };
+/// Advances past a token with the given tag, or fails if it isn't the
+/// current token.
+fn expect_token(state: &mut PS, tag: TokenTag) -> anyhow::Result<()> {
+    if state.matches(tag)? {
+        Ok(())
+    } else {
+        Err(unexpected_token(state))
+    }
+}
 
-    Ok(BuiltIn::History(limit))
+/// Determines if the current token can start a command. Reserved words which
+/// end a compound command can't.
+fn is_command_start(state: &PS) -> bool {
+    match state.current.tag {
+        TokenTag::Arithmetic | TokenTag::FunctionName | TokenTag::LeftParen => true,
+        TokenTag::Word => !matches!(
+            state.current.lexeme.as_ref(),
+            "do" | "done" | "elif" | "else" | "esac" | "fi" | "then" | "}"
+        ),
+        _ => false,
+    }
 }
 
-/// Parses a pwd command.
-fn pwd(state: &mut PS) -> anyhow::Result<BuiltIn> {
-    assert!(state.current.tag == TokenTag::Word);
-    assert!(state.current.lexeme == "pwd");
-    state.advance()?;
-    Ok(BuiltIn::Pwd)
+/// Determines if the current token starts a compound command, which can be
+/// a function's body.
+fn is_compound_start(state: &PS) -> bool {
+    match state.current.tag {
+        TokenTag::Arithmetic | TokenTag::LeftParen => true,
+        TokenTag::Word => matches!(
+            state.current.lexeme.as_ref(),
+            "{" | "[[" | "case" | "for" | "if" | "until" | "while"
+        ),
+        _ => false,
+    }
 }
 
-/// Parses the `type` builtin.
-fn type_builtin(state: &mut PS) -> anyhow::Result<BuiltIn> {
-    assert!(state.current.tag == TokenTag::Word);
-    assert!(state.current.lexeme == "type");
-    state.advance()?;
-    let command = state.expect_lexeme(TokenTag::Word)?;
-    Ok(BuiltIn::Type(command))
+/// Determines if a name is a reserved word, which can't name a function.
+fn is_keyword_name(name: &str) -> bool {
+    matches!(
+        name,
+        "!" | "case"
+            | "do"
+            | "done"
+            | "elif"
+            | "else"
+            | "esac"
+            | "fi"
+            | "for"
+            | "function"
+            | "if"
+            | "in"
+            | "then"
+            | "until"
+            | "while"
+            | "{"
+            | "}"
+            | "[["
+            | "]]"
+    )
 }
 
-/// Collects tokens into a vector as long as they are Word or Integer.
-fn collect_integer_word(state: &mut PS) -> anyhow::Result<Vec<String>> {
-    let mut items = Vec::new();
-    while let TokenTag::Word | TokenTag::Integer(_) = state.current.tag {
-        items.push(state.current.lexeme.clone());
-        state.advance()?;
+/// Creates an error for an unexpected current token.
+fn unexpected_token(state: &PS) -> anyhow::Error {
+    match state.current.tag {
+        TokenTag::EndOfCommand => {
+            IncompleteError::at_end("syntax error: unexpected end of command").into()
+        }
+        TokenTag::Word => anyhow!(
+            "syntax error near unexpected token `{}`",
+            state.current.lexeme
+        ),
+        tag => anyhow!("syntax error near unexpected token `{}`", tag),
     }
-    Ok(items)
 }
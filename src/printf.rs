@@ -0,0 +1,634 @@
+//! The `printf` builtin, which formats its arguments, e.g.
+//! `printf '%-10s %5.2f\n' "$name" "$price"`.
+
+use std::ffi::CString;
+use std::io::Write;
+use std::iter::Peekable;
+use std::mem;
+use std::str::Chars;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::expand::quote_special;
+use crate::scanner::is_name;
+use crate::shell::Shell;
+
+const USAGE: &str = "printf: usage: printf [-v var] format [arguments]";
+
+/// The arguments being formatted, which conversions take in order.
+struct Arguments<'a> {
+    args: &'a [String],
+
+    /// Index of the next argument to take.
+    next: usize,
+
+    /// Diagnostics for arguments which weren't valid numbers.
+    errors: Vec<String>,
+
+    /// Seconds since the epoch when the shell started, for `%(...)T` with
+    /// `-2`.
+    start_time: u64,
+}
+
+impl<'a> Arguments<'a> {
+    /// Takes the next argument, which is empty if they've all been taken.
+    fn string(&mut self) -> &'a str {
+        match self.args.get(self.next) {
+            Some(arg) => {
+                self.next += 1;
+                arg
+            }
+            None => "",
+        }
+    }
+
+    /// Takes the next argument as an integer, which is zero if they've all
+    /// been taken.
+    fn integer(&mut self) -> i64 {
+        let arg = self.string();
+        let (value, error) = parse_integer(arg);
+        if let Some(error) = error {
+            self.errors.push(format!("{arg}: {error}"));
+        }
+        value
+    }
+
+    /// Takes the next argument as a floating point number.
+    fn float(&mut self) -> f64 {
+        let arg = self.string();
+        let (value, error) = parse_float(arg);
+        if let Some(error) = error {
+            self.errors.push(format!("{arg}: {error}"));
+        }
+        value
+    }
+
+    /// Takes the next argument as a time in seconds since the epoch. `-1`,
+    /// or no argument, is the current time, and `-2` is when the shell
+    /// started.
+    fn time(&mut self) -> i64 {
+        let time = match self.args.get(self.next) {
+            Some(_) => self.integer(),
+            None => -1,
+        };
+        match time {
+            -1 => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_secs() as i64),
+            -2 => self.start_time as i64,
+            time => time,
+        }
+    }
+}
+
+/// A conversion's flags, field width and precision, e.g. `-8.3` in `%-8.3f`.
+#[derive(Default)]
+struct Spec {
+    flags: String,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    fn has(&self, flag: char) -> bool {
+        self.flags.contains(flag)
+    }
+}
+
+/// Evaluates the `printf` builtin. The format is reused until the arguments
+/// have all been taken, and conversions without arguments are empty or zero.
+/// With `-v`, the output is assigned to a variable rather than printed.
+/// Returns a failing status if an argument isn't a valid number.
+pub fn printf<TOut: Write, TErr: Write>(
+    shell: &mut Shell,
+    args: &[String],
+    stdout: &mut TOut,
+    stderr: &mut TErr,
+) -> anyhow::Result<i32> {
+    let mut variable = None;
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        if arg == "--" {
+            i += 1;
+            break;
+        }
+        if arg == "-v" {
+            let Some(name) = args.get(i + 1) else {
+                writeln!(stderr, "printf: -v: option requires an argument")?;
+                writeln!(stderr, "{USAGE}")?;
+                return Ok(2);
+            };
+            variable = Some(name.as_str());
+            i += 2;
+        } else if let Some(name) = arg.strip_prefix("-v") {
+            variable = Some(name);
+            i += 1;
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            writeln!(stderr, "printf: {}: invalid option", &arg[..2])?;
+            writeln!(stderr, "{USAGE}")?;
+            return Ok(2);
+        } else {
+            break;
+        }
+    }
+
+    let Some(format) = args.get(i) else {
+        writeln!(stderr, "{USAGE}")?;
+        return Ok(2);
+    };
+    if let Some(name) = variable.filter(|name| !is_name(name)) {
+        writeln!(stderr, "printf: `{name}': not a valid identifier")?;
+        return Ok(2);
+    }
+
+    let mut arguments = Arguments {
+        args: &args[i + 1..],
+        next: 0,
+        errors: Vec::new(),
+        start_time: shell.start_time,
+    };
+    let mut output = Vec::new();
+    let mut status = 0;
+    loop {
+        let next = arguments.next;
+        match format_once(format, &mut arguments, &mut output) {
+            Ok(false) => {}
+            Ok(true) => break,
+            Err(error) => {
+                arguments.errors.push(error);
+                status = 1;
+                break;
+            }
+        }
+        if arguments.next == next || arguments.next == arguments.args.len() {
+            break;
+        }
+    }
+
+    for error in &arguments.errors {
+        writeln!(stderr, "printf: {error}")?;
+        status = 1;
+    }
+    match variable {
+        Some(name) => shell.set_variable(name, String::from_utf8_lossy(&output).into_owned())?,
+        None => stdout.write_all(&output)?,
+    }
+    Ok(status)
+}
+
+/// Expands backslash escapes in a text, as `%b` and `echo -e` do. Returns the
+/// bytes, and whether `\c` stopped the output there.
+pub fn expand_escapes(text: &str) -> (Vec<u8>, bool) {
+    let mut output = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if escape(&mut chars, true, &mut output) => return (output, true),
+            '\\' => {}
+            c => push_char(&mut output, c),
+        }
+    }
+    (output, false)
+}
+
+/// Writes the output of the format once, taking arguments for its
+/// conversions. Returns true if `\c` in a `%b` argument stopped the output,
+/// or an error for an invalid conversion.
+fn format_once(
+    format: &str,
+    arguments: &mut Arguments,
+    output: &mut Vec<u8>,
+) -> Result<bool, String> {
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                escape(&mut chars, false, output);
+            }
+            '%' if convert(&mut chars, arguments, output)? => return Ok(true),
+            '%' => {}
+            c => push_char(output, c),
+        }
+    }
+    Ok(false)
+}
+
+/// Writes the output of a conversion, after its `%`. Returns true if `\c` in
+/// a `%b` argument stopped the output.
+fn convert(
+    chars: &mut Peekable<Chars>,
+    arguments: &mut Arguments,
+    output: &mut Vec<u8>,
+) -> Result<bool, String> {
+    if chars.next_if_eq(&'%').is_some() {
+        output.push(b'%');
+        return Ok(false);
+    }
+
+    let mut spec = Spec::default();
+    while let Some(flag) = chars.next_if(|c| "-+ #0'".contains(*c)) {
+        spec.flags.push(flag);
+    }
+    if chars.next_if_eq(&'*').is_some() {
+        // A negative width left-justifies.
+        let width = arguments.integer();
+        if width < 0 {
+            spec.flags.push('-');
+        }
+        spec.width = bounded(width.unsigned_abs(), "field width")?;
+    } else {
+        let width = number(chars, 10, usize::MAX).unwrap_or(0);
+        spec.width = bounded(width.into(), "field width")?;
+    }
+    if chars.next_if_eq(&'.').is_some() {
+        // A negative precision is ignored.
+        spec.precision = if chars.next_if_eq(&'*').is_some() {
+            match u64::try_from(arguments.integer()) {
+                Ok(precision) => Some(bounded(precision, "precision")?),
+                Err(_) => None,
+            }
+        } else {
+            let precision = number(chars, 10, usize::MAX).unwrap_or(0);
+            Some(bounded(precision.into(), "precision")?)
+        };
+    }
+    // Length modifiers, e.g. the `l` of `%ld`, make no difference.
+    while chars.next_if(|c| "hjlLtz".contains(*c)).is_some() {}
+
+    let time_format = chars
+        .next_if_eq(&'(')
+        .map(|_| chars.by_ref().take_while(|c| *c != ')').collect::<String>());
+    let Some(conversion) = chars.next() else {
+        return Err(String::from("`%': missing format character"));
+    };
+
+    let text = match (conversion, time_format) {
+        ('T', Some(format)) => truncate(&spec, format_time(&format, arguments.time())),
+        (_, Some(_)) => return Err(format!("`{conversion}': invalid time format specification")),
+        ('s', None) => truncate(&spec, arguments.string().as_bytes().to_vec()),
+        ('b', None) => {
+            let (bytes, is_stopped) = expand_escapes(arguments.string());
+            pad(&spec, &truncate(&spec, bytes), output);
+            return Ok(is_stopped);
+        }
+        ('q', None) => quote_special(arguments.string()).into_bytes(),
+        ('c', None) => arguments
+            .string()
+            .chars()
+            .next()
+            .map(String::from)
+            .unwrap_or_default()
+            .into_bytes(),
+        ('d' | 'i', None) => format_signed(&spec, arguments.integer()).into_bytes(),
+        ('u' | 'o' | 'x' | 'X', None) => {
+            format_unsigned(&spec, arguments.integer() as u64, conversion).into_bytes()
+        }
+        ('e' | 'E' | 'f' | 'F' | 'g' | 'G', None) => {
+            format_float(&spec, arguments.float(), conversion).into_bytes()
+        }
+        (c, None) => return Err(format!("`{c}': invalid format character")),
+    };
+    pad(&spec, &text, output);
+    Ok(false)
+}
+
+/// Writes a backslash escape's character, after the backslash. `\c` and
+/// octal escapes starting with `\0` are escapes in arguments of `%b`, while
+/// other octal escapes and escaped quotes are escapes in formats. Returns
+/// true for `\c`.
+fn escape(chars: &mut Peekable<Chars>, is_argument: bool, output: &mut Vec<u8>) -> bool {
+    let Some(c) = chars.next() else {
+        output.push(b'\\');
+        return false;
+    };
+    let byte = match c {
+        'a' => 0x07,
+        'b' => 0x08,
+        'e' | 'E' => 0x1b,
+        'f' => 0x0c,
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        'v' => 0x0b,
+        '\\' => b'\\',
+        '"' | '\'' | '?' if !is_argument => c as u8,
+        'c' if is_argument => return true,
+        '0' if is_argument => number(chars, 8, 3).unwrap_or(0) as u8,
+        '0'..='7' if !is_argument => {
+            let first = c.to_digit(8).unwrap_or(0);
+            let rest = number_with_count(chars, 8, 2);
+            (first * 8u32.pow(rest.1 as u32) + rest.0) as u8
+        }
+        'x' | 'u' | 'U' => {
+            let max = match c {
+                'x' => 2,
+                'u' => 4,
+                _ => 8,
+            };
+            match number(chars, 16, max) {
+                Some(n) if c == 'x' => n as u8,
+                Some(n) => {
+                    push_char(
+                        output,
+                        char::from_u32(n).unwrap_or(char::REPLACEMENT_CHARACTER),
+                    );
+                    return false;
+                }
+                None => {
+                    output.push(b'\\');
+                    push_char(output, c);
+                    return false;
+                }
+            }
+        }
+        c => {
+            output.push(b'\\');
+            push_char(output, c);
+            return false;
+        }
+    };
+    output.push(byte);
+    false
+}
+
+/// Reads up to `max` digits in a radix. Returns their value, or `None` if
+/// there weren't any.
+fn number(chars: &mut Peekable<Chars>, radix: u32, max: usize) -> Option<u32> {
+    match number_with_count(chars, radix, max) {
+        (_, 0) => None,
+        (value, _) => Some(value),
+    }
+}
+
+/// Reads up to `max` digits in a radix. Returns their value and how many
+/// there were.
+fn number_with_count(chars: &mut Peekable<Chars>, radix: u32, max: usize) -> (u32, usize) {
+    let mut value: u32 = 0;
+    let mut count = 0;
+    while count < max {
+        let Some(digit) = chars.peek().and_then(|c| c.to_digit(radix)) else {
+            break;
+        };
+        chars.next();
+        value = value.saturating_mul(radix).saturating_add(digit);
+        count += 1;
+    }
+    (value, count)
+}
+
+/// Checks a field width or precision is within bash's limit, so output
+/// isn't padded to an unreasonable size.
+fn bounded(value: u64, what: &str) -> Result<usize, String> {
+    match value {
+        0..=0x7fff_ffff => Ok(value as usize),
+        _ => Err(format!("invalid {what}")),
+    }
+}
+
+fn push_char(output: &mut Vec<u8>, c: char) {
+    let mut buffer = [0; 4];
+    output.extend(c.encode_utf8(&mut buffer).as_bytes());
+}
+
+/// Cuts a string's bytes to the conversion's precision.
+fn truncate(spec: &Spec, mut bytes: Vec<u8>) -> Vec<u8> {
+    if let Some(precision) = spec.precision {
+        bytes.truncate(precision);
+    }
+    bytes
+}
+
+/// Writes a conversion's output, padded with spaces to its width.
+fn pad(spec: &Spec, text: &[u8], output: &mut Vec<u8>) {
+    let padding = vec![b' '; spec.width.saturating_sub(text.len())];
+    if spec.has('-') {
+        output.extend(text);
+        output.extend(padding);
+    } else {
+        output.extend(padding);
+        output.extend(text);
+    }
+}
+
+fn format_signed(spec: &Spec, value: i64) -> String {
+    let sign = match value {
+        ..=-1 => "-",
+        _ if spec.has('+') => "+",
+        _ if spec.has(' ') => " ",
+        _ => "",
+    };
+    let digits = with_precision(spec, value.unsigned_abs().to_string());
+    zero_pad(spec, sign, &digits, spec.precision.is_none())
+}
+
+/// Formats an unsigned conversion, in which negative numbers wrap around.
+fn format_unsigned(spec: &Spec, value: u64, conversion: char) -> String {
+    let digits = match conversion {
+        'o' => format!("{value:o}"),
+        'x' => format!("{value:x}"),
+        'X' => format!("{value:X}"),
+        _ => value.to_string(),
+    };
+    let mut digits = with_precision(spec, digits);
+
+    // The `#` flag shows the base.
+    let mut prefix = "";
+    if spec.has('#') {
+        match conversion {
+            'o' if !digits.starts_with('0') => digits.insert(0, '0'),
+            'x' if value != 0 => prefix = "0x",
+            'X' if value != 0 => prefix = "0X",
+            _ => {}
+        }
+    }
+    zero_pad(spec, prefix, &digits, spec.precision.is_none())
+}
+
+/// Pads an integer's digits with zeros to the precision, which is the least
+/// number of digits.
+fn with_precision(spec: &Spec, digits: String) -> String {
+    match spec.precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(precision) => format!("{digits:0>precision$}"),
+        None => digits,
+    }
+}
+
+/// Joins a number's sign or prefix to its digits, with zeros between them to
+/// fill the width if the `0` flag was given.
+fn zero_pad(spec: &Spec, prefix: &str, digits: &str, is_paddable: bool) -> String {
+    let length = prefix.len() + digits.len();
+    if is_paddable && spec.has('0') && !spec.has('-') && spec.width > length {
+        let zeros = "0".repeat(spec.width - length);
+        format!("{prefix}{zeros}{digits}")
+    } else {
+        format!("{prefix}{digits}")
+    }
+}
+
+fn format_float(spec: &Spec, value: f64, conversion: char) -> String {
+    let precision = spec.precision.unwrap_or(6);
+    let magnitude = if value.is_nan() {
+        String::from("nan")
+    } else if value.is_infinite() {
+        String::from("inf")
+    } else {
+        match conversion.to_ascii_lowercase() {
+            'e' => exponential(value.abs(), precision),
+            'f' => format!("{:.precision$}", value.abs()),
+            _ => general(value.abs(), precision, spec.has('#')),
+        }
+    };
+    let magnitude = match conversion.is_ascii_uppercase() {
+        true => magnitude.to_ascii_uppercase(),
+        false => magnitude,
+    };
+
+    let sign = match value.is_sign_negative() {
+        true => "-",
+        false if spec.has('+') => "+",
+        false if spec.has(' ') => " ",
+        false => "",
+    };
+    zero_pad(spec, sign, &magnitude, value.is_finite())
+}
+
+/// Formats a number with one digit before the point and an exponent of at
+/// least two digits, e.g. `1.500000e+03`.
+fn exponential(value: f64, precision: usize) -> String {
+    let text = format!("{value:.precision$e}");
+    let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{mantissa}e{sign}{:02}", exponent.abs())
+}
+
+/// Formats a number to a precision of significant digits, with an exponent
+/// only if it's very large or small. Trailing zeros are removed unless the
+/// `#` flag was given.
+fn general(value: f64, precision: usize, is_alternate: bool) -> String {
+    let precision = precision.max(1);
+    let exponent = match value {
+        0.0 => 0,
+        value => format!("{value:.*e}", precision - 1)
+            .split_once('e')
+            .and_then(|(_, exponent)| exponent.parse::<i64>().ok())
+            .unwrap_or(0),
+    };
+    let text = if exponent < -4 || exponent >= precision as i64 {
+        exponential(value, precision - 1)
+    } else {
+        let decimals = (precision as i64 - 1 - exponent) as usize;
+        format!("{value:.decimals$}")
+    };
+    if is_alternate {
+        return text;
+    }
+
+    let (number, exponent) = text.split_at(text.find('e').unwrap_or(text.len()));
+    if !number.contains('.') {
+        return text;
+    }
+    let number = number.trim_end_matches('0').trim_end_matches('.');
+    format!("{number}{exponent}")
+}
+
+/// Formats a time in seconds since the epoch with `strftime`. An empty
+/// format is the time of day.
+fn format_time(format: &str, time: i64) -> Vec<u8> {
+    let format = if format.is_empty() { "%X" } else { format };
+    let Ok(format) = CString::new(format) else {
+        return Vec::new();
+    };
+    let time = time as libc::time_t;
+    let mut buffer = vec![0u8; 1024];
+
+    // SAFETY: `tm` is plain data, which `localtime_r` fills in, and
+    // `strftime` writes at most the buffer's length.
+    let length = unsafe {
+        let mut tm: libc::tm = mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return Vec::new();
+        }
+        libc::strftime(
+            buffer.as_mut_ptr().cast(),
+            buffer.len(),
+            format.as_ptr(),
+            &tm,
+        )
+    };
+    buffer.truncate(length);
+    buffer
+}
+
+/// Parses an integer argument as C's `strtol` does, e.g. with a leading `0x`
+/// for hexadecimal or `0` for octal. An argument starting with a quote is the
+/// code of the character after it. Returns the value of the valid part with
+/// an error if there's more.
+fn parse_integer(arg: &str) -> (i64, Option<&'static str>) {
+    if let Some(code) = character_code(arg) {
+        return (code, None);
+    }
+    if arg.is_empty() {
+        return (0, None);
+    }
+
+    let text = arg.trim_start();
+    let (is_negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (radix, digits) =
+        if let Some(digits) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            (16, digits)
+        } else if text.len() > 1 && text.starts_with('0') {
+            (8, &text[1..])
+        } else {
+            (10, text)
+        };
+
+    let mut magnitude: i128 = 0;
+    let mut count = 0;
+    for digit in digits.chars().map_while(|c| c.to_digit(radix)) {
+        magnitude = (magnitude * radix as i128 + digit as i128).min(i64::MAX as i128 + 1);
+        count += 1;
+    }
+    let value = if is_negative { -magnitude } else { magnitude };
+
+    let is_valid = count > 0 && count == digits.len();
+    let is_in_range = (i64::MIN as i128..=i64::MAX as i128).contains(&value);
+    let value = value.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+    match (is_valid, is_in_range) {
+        (false, _) => (value, Some("invalid number")),
+        (true, false) => (value, Some("Result too large")),
+        (true, true) => (value, None),
+    }
+}
+
+/// Parses a floating point argument. Returns the value of the longest valid
+/// part with an error if there's more.
+fn parse_float(arg: &str) -> (f64, Option<&'static str>) {
+    if let Some(code) = character_code(arg) {
+        return (code as f64, None);
+    }
+    if arg.is_empty() {
+        return (0.0, None);
+    }
+
+    let text = arg.trim_start();
+    for end in (1..=text.len())
+        .rev()
+        .filter(|end| text.is_char_boundary(*end))
+    {
+        if let Ok(value) = text[..end].parse() {
+            return (value, (end < text.len()).then_some("invalid number"));
+        }
+    }
+    (0.0, Some("invalid number"))
+}
+
+/// Gets the code of the character after a leading quote, e.g. `'A`, which
+/// numeric conversions take as the number.
+fn character_code(arg: &str) -> Option<i64> {
+    let rest = arg.strip_prefix(['\'', '"'])?;
+    Some(rest.chars().next().map_or(0, |c| c as i64))
+}
@@ -0,0 +1,351 @@
+//! The `read` builtin, which reads a line of input into variables, e.g.
+//! `read -r first rest`.
+
+use std::io::{self, Write};
+use std::mem;
+use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
+
+use crate::scanner::is_name;
+use crate::shell::Shell;
+use crate::streams::Input;
+
+const USAGE: &str =
+    "read: usage: read [-rs] [-a array] [-d delim] [-n nchars] [-p prompt] [-t timeout] [name ...]";
+
+/// Exit status when the input isn't read before the timeout, as if the
+/// shell had been sent `SIGALRM`.
+const TIMEOUT_STATUS: i32 = 128 + libc::SIGALRM;
+
+/// Options given to `read`.
+struct Options {
+    /// `-r` was given, so backslashes don't escape characters.
+    is_raw: bool,
+
+    /// `-s` was given, so a terminal doesn't echo the input.
+    is_silent: bool,
+
+    /// Array to read the fields into, from `-a`.
+    array: Option<String>,
+
+    /// Character which ends the input, from `-d`.
+    delimiter: char,
+
+    /// Most characters to read, from `-n`.
+    count: Option<usize>,
+
+    /// Printed before reading from a terminal, from `-p`.
+    prompt: Option<String>,
+
+    /// How long to wait for the input, from `-t`.
+    timeout: Option<Duration>,
+}
+
+/// A character of input, which is taken literally if it was escaped with a
+/// backslash.
+struct InputChar {
+    c: char,
+    is_escaped: bool,
+}
+
+/// Evaluates the `read` builtin. A line of input is split into fields by
+/// `$IFS`, which are assigned to the names in order, with the last name
+/// getting the rest of the line. Without names, the line is assigned to
+/// `REPLY`. Returns a failing status at the end of the input.
+pub fn read<TErr: Write>(
+    shell: &mut Shell,
+    args: &[String],
+    stdin: &mut Input,
+    stderr: &mut TErr,
+) -> anyhow::Result<i32> {
+    let Some((options, count)) = parse_options(args, stderr)? else {
+        return Ok(2);
+    };
+    let names = &args[count..];
+    if let Some(name) = names
+        .iter()
+        .chain(&options.array)
+        .find(|name| !is_name(name))
+    {
+        writeln!(stderr, "read: `{name}': not a valid identifier")?;
+        return Ok(1);
+    }
+
+    // A zero timeout only checks if there's input to read.
+    if options.timeout == Some(Duration::ZERO) {
+        return Ok(!stdin.wait(Duration::ZERO)? as i32);
+    }
+
+    // SAFETY: `isatty` only inspects the descriptor, and an invalid one
+    // just makes it return 0.
+    let terminal = stdin.fd().filter(|fd| unsafe { libc::isatty(*fd) } == 1);
+    if let (Some(prompt), Some(_)) = (&options.prompt, terminal) {
+        write!(stderr, "{prompt}")?;
+        stderr.flush()?;
+    }
+
+    let (chars, status) = {
+        let _mode = match terminal {
+            Some(fd) if options.is_silent || options.count.is_some() => {
+                TerminalMode::set(fd, options.is_silent, options.count.is_some())
+            }
+            _ => None,
+        };
+        read_chars(stdin, &options)?
+    };
+
+    let ifs = shell.ifs();
+    if let Some(array) = &options.array {
+        let fields = split_fields(&chars, &ifs, None);
+        shell.set_array(
+            array,
+            fields.into_iter().map(|field| (None, field)).collect(),
+        )?;
+    } else if names.is_empty() {
+        shell.set_variable("REPLY", chars.iter().map(|c| c.c).collect())?;
+    } else {
+        let fields = split_fields(&chars, &ifs, Some(names.len()));
+        for (i, name) in names.iter().enumerate() {
+            let field = fields.get(i).cloned().unwrap_or_default();
+            shell.set_variable(name, field)?;
+        }
+    }
+    Ok(status)
+}
+
+/// Parses the options before the names. Returns the options and the number
+/// of arguments they took, or `None` if they're invalid.
+fn parse_options<TErr: Write>(
+    args: &[String],
+    stderr: &mut TErr,
+) -> anyhow::Result<Option<(Options, usize)>> {
+    let mut options = Options {
+        is_raw: false,
+        is_silent: false,
+        array: None,
+        delimiter: '\n',
+        count: None,
+        prompt: None,
+        timeout: None,
+    };
+
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        if arg == "--" {
+            i += 1;
+            break;
+        }
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            break;
+        };
+        i += 1;
+
+        for (j, flag) in flags.char_indices() {
+            match flag {
+                'r' => options.is_raw = true,
+                's' => options.is_silent = true,
+                'a' | 'd' | 'n' | 'p' | 't' => {
+                    // The option's value is the rest of the argument or the
+                    // next argument.
+                    let rest = &flags[j + 1..];
+                    let value = if !rest.is_empty() {
+                        rest.to_string()
+                    } else if let Some(value) = args.get(i) {
+                        i += 1;
+                        value.clone()
+                    } else {
+                        writeln!(stderr, "read: -{flag}: option requires an argument")?;
+                        writeln!(stderr, "{USAGE}")?;
+                        return Ok(None);
+                    };
+
+                    match flag {
+                        'a' => options.array = Some(value),
+                        // An empty delimiter ends the input at a NUL byte.
+                        'd' => options.delimiter = value.chars().next().unwrap_or('\0'),
+                        'n' => match value.parse() {
+                            Ok(count) => options.count = Some(count),
+                            Err(_) => {
+                                writeln!(stderr, "read: {value}: invalid number")?;
+                                return Ok(None);
+                            }
+                        },
+                        'p' => options.prompt = Some(value),
+                        _ => match value.parse::<f64>() {
+                            Ok(seconds) if seconds >= 0.0 && seconds.is_finite() => {
+                                options.timeout = Some(Duration::from_secs_f64(seconds));
+                            }
+                            _ => {
+                                writeln!(stderr, "read: {value}: invalid timeout specification")?;
+                                return Ok(None);
+                            }
+                        },
+                    }
+                    break;
+                }
+                _ => {
+                    writeln!(stderr, "read: -{flag}: invalid option")?;
+                    writeln!(stderr, "{USAGE}")?;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    Ok(Some((options, i)))
+}
+
+/// Reads characters up to the delimiter, which isn't included. Returns them
+/// with the exit status, which fails if the input ended first or the
+/// timeout passed.
+fn read_chars(stdin: &mut Input, options: &Options) -> io::Result<(Vec<InputChar>, i32)> {
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let mut chars = Vec::new();
+    let mut is_escaped = false;
+    loop {
+        if options.count.is_some_and(|count| chars.len() >= count) {
+            return Ok((chars, 0));
+        }
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !stdin.wait(remaining)? {
+                return Ok((chars, TIMEOUT_STATUS));
+            }
+        }
+        let Some(c) = read_char(stdin)? else {
+            return Ok((chars, 1));
+        };
+
+        if is_escaped {
+            is_escaped = false;
+            // A backslash-newline continues the line.
+            if c != '\n' {
+                chars.push(InputChar {
+                    c,
+                    is_escaped: true,
+                });
+            }
+        } else if c == '\\' && !options.is_raw {
+            is_escaped = true;
+        } else if c == options.delimiter {
+            return Ok((chars, 0));
+        } else {
+            chars.push(InputChar {
+                c,
+                is_escaped: false,
+            });
+        }
+    }
+}
+
+/// Reads a UTF-8 character. Invalid bytes are read as the replacement
+/// character.
+fn read_char(stdin: &mut Input) -> io::Result<Option<char>> {
+    let Some(first) = stdin.read_byte()? else {
+        return Ok(None);
+    };
+    let length = match first.leading_ones() {
+        2..=4 => first.leading_ones() as usize,
+        _ => 1,
+    };
+    let mut bytes = vec![first];
+    while bytes.len() < length {
+        match stdin.read_byte()? {
+            Some(byte) => bytes.push(byte),
+            None => break,
+        }
+    }
+    Ok(String::from_utf8_lossy(&bytes).chars().next())
+}
+
+/// Splits input into fields at unescaped characters of `$IFS`, as in field
+/// splitting. Given a count of fields, the last one is the rest of the
+/// input.
+fn split_fields(chars: &[InputChar], ifs: &str, count: Option<usize>) -> Vec<String> {
+    let is_ifs = |c: &InputChar| !c.is_escaped && ifs.contains(c.c);
+    let is_ifs_whitespace = |c: &InputChar| is_ifs(c) && matches!(c.c, ' ' | '\t' | '\n');
+    let text = |chars: &[InputChar]| chars.iter().map(|c| c.c).collect::<String>();
+
+    // IFS whitespace around the input is ignored.
+    let start = chars
+        .iter()
+        .position(|c| !is_ifs_whitespace(c))
+        .unwrap_or(chars.len());
+    let end = chars
+        .iter()
+        .rposition(|c| !is_ifs_whitespace(c))
+        .map_or(start, |i| i + 1);
+    let chars = &chars[start..end];
+
+    let mut fields = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if count.is_some_and(|count| fields.len() + 1 == count) {
+            fields.push(text(&chars[i..]));
+            break;
+        }
+        let end = chars[i..]
+            .iter()
+            .position(is_ifs)
+            .map_or(chars.len(), |n| i + n);
+        fields.push(text(&chars[i..end]));
+
+        // A field ends at IFS whitespace or at one other IFS character,
+        // with any IFS whitespace around it.
+        i = end;
+        while chars.get(i).is_some_and(is_ifs_whitespace) {
+            i += 1;
+        }
+        if chars
+            .get(i)
+            .is_some_and(|c| is_ifs(c) && !is_ifs_whitespace(c))
+        {
+            i += 1;
+            while chars.get(i).is_some_and(is_ifs_whitespace) {
+                i += 1;
+            }
+        }
+    }
+    fields
+}
+
+/// Changes how a terminal reads input until it's dropped, e.g. so it doesn't
+/// echo the input for `-s`.
+struct TerminalMode {
+    fd: RawFd,
+    saved: libc::termios,
+}
+
+impl TerminalMode {
+    /// Turns off the terminal's echo if silent, and its line buffering if
+    /// unbuffered, so characters are read as they're typed, e.g. for `-n`.
+    /// Returns `None` if it isn't a terminal.
+    fn set(fd: RawFd, is_silent: bool, is_unbuffered: bool) -> Option<TerminalMode> {
+        // SAFETY: `termios` is plain data, which `tcgetattr` fills in.
+        let saved = unsafe {
+            let mut saved: libc::termios = mem::zeroed();
+            if libc::tcgetattr(fd, &mut saved) != 0 {
+                return None;
+            }
+            saved
+        };
+
+        let mut termios = saved;
+        if is_silent {
+            termios.c_lflag &= !libc::ECHO;
+        }
+        if is_unbuffered {
+            termios.c_lflag &= !libc::ICANON;
+            termios.c_cc[libc::VMIN] = 1;
+            termios.c_cc[libc::VTIME] = 0;
+        }
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) };
+        Some(TerminalMode { fd, saved })
+    }
+}
+
+impl Drop for TerminalMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.saved) };
+    }
+}
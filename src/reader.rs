@@ -0,0 +1,148 @@
+//! Reading complete commands from lines of text, e.g. of a script.
+
+use std::io::{self, BufRead, Lines, Read};
+
+use crate::error::incomplete_closer;
+use crate::parser::parse;
+
+/// Reads command texts from lines of text. Each command text has as many
+/// lines as its commands need to be complete, e.g. all the lines of an `if`
+/// command, so commands can be evaluated before later lines are read.
+pub struct CommandReader<R> {
+    lines: Lines<R>,
+
+    /// Number of lines read so far.
+    line: usize,
+
+    /// Commands are scanned with extended pattern groups, e.g. `@(a|b)`.
+    is_extglob: bool,
+}
+
+impl<R: BufRead> CommandReader<R> {
+    pub fn new(reader: R) -> CommandReader<R> {
+        CommandReader {
+            lines: reader.lines(),
+            line: 0,
+            is_extglob: false,
+        }
+    }
+
+    /// Sets whether the next commands are scanned with extended pattern
+    /// groups, since `extglob` may be changed by the commands before them.
+    pub fn set_extglob(&mut self, is_extglob: bool) {
+        self.is_extglob = is_extglob;
+    }
+}
+
+impl<R: BufRead> Iterator for CommandReader<R> {
+    /// A command text and the number of the line it starts on.
+    type Item = io::Result<(String, usize)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first_line = self.line + 1;
+        let mut command_text = String::new();
+
+        // Text which a line needs to complete the command, e.g. `fi`. Lines
+        // without it can't, so the command isn't parsed again until there's
+        // one, rather than after every line.
+        let mut closer = "";
+        for line in self.lines.by_ref() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            self.line += 1;
+            command_text.push_str(&line);
+            if !line.contains(closer) {
+                command_text.push('\n');
+                continue;
+            }
+            if let Err(e) = parse(&command_text, self.is_extglob) {
+                if let Some(incomplete_closer) = incomplete_closer(&e) {
+                    closer = incomplete_closer;
+                    command_text.push('\n');
+                    continue;
+                }
+            }
+            return Some(Ok((command_text, first_line)));
+        }
+
+        // Commands left incomplete are still returned, so evaluating them
+        // reports the error.
+        if command_text.is_empty() {
+            None
+        } else {
+            Some(Ok((command_text, first_line)))
+        }
+    }
+}
+
+/// Reads the shell's stdin, e.g. a script piped to it, without reading past
+/// the end of the current line, so commands which read stdin, e.g. `read` or
+/// `cat`, get the rest of it. Stdin is read a byte at a time, unless it can
+/// seek, e.g. for a file, when it's read in blocks and the offset is moved
+/// back to the end of the line.
+pub struct StdinReader {
+    buffer: Vec<u8>,
+
+    /// Index of the first byte in the buffer which hasn't been consumed.
+    position: usize,
+
+    is_seekable: bool,
+}
+
+impl StdinReader {
+    pub fn new() -> StdinReader {
+        let is_seekable = unsafe { libc::lseek(libc::STDIN_FILENO, 0, libc::SEEK_CUR) } != -1;
+        StdinReader {
+            buffer: Vec::new(),
+            position: 0,
+            is_seekable,
+        }
+    }
+}
+
+impl Read for StdinReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for StdinReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.position < self.buffer.len() {
+            return Ok(&self.buffer[self.position..]);
+        }
+
+        let size = if self.is_seekable { 4096 } else { 1 };
+        self.buffer.resize(size, 0);
+        self.position = 0;
+        let n = loop {
+            let n =
+                unsafe { libc::read(libc::STDIN_FILENO, self.buffer.as_mut_ptr().cast(), size) };
+            match n {
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+                -1 => return Err(io::Error::last_os_error()),
+                n => break n as usize,
+            }
+        };
+        self.buffer.truncate(n);
+
+        // Bytes after the line are given back, so the offset is at the start
+        // of the next line.
+        if let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let extra = self.buffer.len() - end - 1;
+            if extra > 0 {
+                unsafe { libc::lseek(libc::STDIN_FILENO, -(extra as libc::off_t), libc::SEEK_CUR) };
+                self.buffer.truncate(end + 1);
+            }
+        }
+        Ok(&self.buffer)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt;
+    }
+}
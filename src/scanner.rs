@@ -1,49 +1,106 @@
 //! Scanner for the command line parser.
 
 use std::fmt;
+use std::mem::take;
+use std::ops::Range;
 use std::str::Chars;
 
 use anyhow::anyhow;
+use parser_state::Lexer;
+
+use crate::ast::{ParameterExpansion, ParameterOperator, Subscript, Word, WordPart};
+use crate::error::IncompleteError;
+use crate::parser::parse_command_substitution;
 
 /// A token type.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TokenTag {
+    /// The `&&` operator.
+    And,
+
+    /// The `&` operator, which runs an and-or list in the background.
+    Ampersand,
+
+    /// A case item terminator `;;&`, which tests the following patterns.
+    CaseContinue,
+
+    /// A case item terminator `;&`, which falls through to the next item.
+    CaseFallThrough,
+
+    /// A case item terminator `;;`.
+    DoubleSemicolon,
+
+    /// An arithmetic command, e.g. `(( i++ ))`. The lexeme is the expression
+    /// between the parentheses.
+    Arithmetic,
+
     /// The end of the command text.
     EndOfCommand,
 
-    /// An integer literal.
-    Integer(u32),
+    /// A word followed by `()`, which starts a function definition. The
+    /// lexeme is the function's name.
+    FunctionName,
+
+    /// A left parenthesis `(`.
+    LeftParen,
+
+    /// A newline, which separates commands like `;`.
+    Newline,
+
+    /// The `||` operator.
+    Or,
 
     /// A pipe operator `|`.
     Pipe,
 
+    /// Input redirection operator `<`.
+    RedirectIn,
+
     /// Output redirection operator `>`.
     RedirectOut,
 
     /// Output append redirection operator `>>`.
     RedirectOutAppend,
 
+    /// A right parenthesis `)`.
+    RightParen,
+
     /// Output redirection opterator with a file descriptor, e.g. `1>`.
     RedirectOutWithFileDescriptor(u32),
 
     /// Output redirection append opterator with a file descriptor, e.g. `1>>`.
     RedirectOutAppendWithFileDescriptor(u32),
 
-    /// A word which is a string of non-whitespace characters that doesn't
-    /// start with a digit.
+    /// A command separator `;`.
+    Semicolon,
+
+    /// A word which is a string of characters that doesn't contain unquoted
+    /// whitespace or operators. The lexeme is the word's source text.
     Word,
 }
 
 impl fmt::Display for TokenTag {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Self::And => write!(f, "&&"),
+            Self::Ampersand => write!(f, "&"),
+            Self::Arithmetic => write!(f, "(("),
+            Self::CaseContinue => write!(f, ";;&"),
+            Self::CaseFallThrough => write!(f, ";&"),
+            Self::DoubleSemicolon => write!(f, ";;"),
             Self::EndOfCommand => write!(f, "End of Command"),
-            Self::Integer(i) => write!(f, "{}", i),
+            Self::FunctionName => write!(f, "()"),
+            Self::LeftParen => write!(f, "("),
+            Self::Newline => write!(f, "newline"),
+            Self::Or => write!(f, "||"),
             Self::Pipe => write!(f, "|"),
+            Self::RedirectIn => write!(f, "<"),
             Self::RedirectOut => write!(f, ">"),
             Self::RedirectOutAppend => write!(f, ">>"),
+            Self::RightParen => write!(f, ")"),
             Self::RedirectOutWithFileDescriptor(i) => write!(f, "{}>", i),
             Self::RedirectOutAppendWithFileDescriptor(i) => write!(f, "{}>>", i),
+            Self::Semicolon => write!(f, ";"),
             Self::Word => write!(f, "Word"),
         }
     }
@@ -57,11 +114,36 @@ pub struct Token {
 
     /// The token's text.
     pub lexeme: String,
+
+    /// The parsed contents of a word token.
+    pub word: Option<Word>,
+
+    /// Byte offsets of the token's source text in the command text.
+    pub span: Range<usize>,
+
+    /// Line number the token starts on.
+    pub line: usize,
 }
 
 impl Token {
     fn new(tag: TokenTag, lexeme: String) -> Token {
-        Token { tag, lexeme }
+        Token {
+            tag,
+            lexeme,
+            word: None,
+            span: 0..0,
+            line: 0,
+        }
+    }
+
+    fn word(lexeme: String, word: Word) -> Token {
+        Token {
+            tag: TokenTag::Word,
+            lexeme,
+            word: Some(word),
+            span: 0..0,
+            line: 0,
+        }
     }
 }
 
@@ -98,6 +180,9 @@ enum WordState {
 
 /// Converts a command's text into a stream of tokens.
 pub struct Scanner<'a> {
+    /// The command text.
+    text: &'a str,
+
     /// An iterator over the command text.
     chars: Chars<'a>,
 
@@ -106,22 +191,88 @@ pub struct Scanner<'a> {
 
     /// Next char after current in the command text.
     next: Option<char>,
+
+    /// Byte offset of the current char in the command text.
+    offset: usize,
+
+    /// Line number of the current char.
+    line: usize,
+
+    /// `extglob` is on, so extended pattern groups, e.g. `@(a|b)`, are
+    /// scanned as parts of words.
+    is_extglob: bool,
 }
 
-impl<'a> parser_state::Lexer for Scanner<'a> {
+impl<'a> Lexer for Scanner<'a> {
     type Token = Token;
 
     /// Returns the next token in the command text.
     fn next_token(&mut self) -> anyhow::Result<Self::Token> {
         self.skip_whitespace();
+        if self.current == Some('#') {
+            self.skip_comment();
+        }
+        let start = self.offset;
+        let line = self.line;
 
-        let token = match self.current {
+        let mut token = match self.current {
             None => Token::new(TokenTag::EndOfCommand, String::from("")),
+            Some('\n') => {
+                self.advance();
+                let lexeme = String::from("\n");
+                Token::new(TokenTag::Newline, lexeme)
+            }
+            Some(';') if matches!(self.next, Some(';')) && self.peek() == Some('&') => {
+                self.advance();
+                self.advance();
+                self.advance();
+                let lexeme = String::from(";;&");
+                Token::new(TokenTag::CaseContinue, lexeme)
+            }
+            Some(';') if matches!(self.next, Some(';')) => {
+                self.advance();
+                self.advance();
+                let lexeme = String::from(";;");
+                Token::new(TokenTag::DoubleSemicolon, lexeme)
+            }
+            Some(';') if matches!(self.next, Some('&')) => {
+                self.advance();
+                self.advance();
+                let lexeme = String::from(";&");
+                Token::new(TokenTag::CaseFallThrough, lexeme)
+            }
+            Some(';') => {
+                self.advance();
+                let lexeme = String::from(";");
+                Token::new(TokenTag::Semicolon, lexeme)
+            }
+            Some('&') if matches!(self.next, Some('&')) => {
+                self.advance();
+                self.advance();
+                let lexeme = String::from("&&");
+                Token::new(TokenTag::And, lexeme)
+            }
+            Some('&') => {
+                self.advance();
+                let lexeme = String::from("&");
+                Token::new(TokenTag::Ampersand, lexeme)
+            }
+            Some('|') if matches!(self.next, Some('|')) => {
+                self.advance();
+                self.advance();
+                let lexeme = String::from("||");
+                Token::new(TokenTag::Or, lexeme)
+            }
             Some('|') => {
                 self.advance();
                 let lexeme = String::from("|");
                 Token::new(TokenTag::Pipe, lexeme)
             }
+            Some('<') => {
+                self.advance();
+                let lexeme = String::from("<");
+                Token::new(TokenTag::RedirectIn, lexeme)
+            }
             Some('>') if matches!(self.next, Some('>')) => {
                 self.advance();
                 self.advance();
@@ -133,13 +284,28 @@ impl<'a> parser_state::Lexer for Scanner<'a> {
                 let lexeme = String::from(">");
                 Token::new(TokenTag::RedirectOut, lexeme)
             }
-            Some(c) if is_digit(c) => self.integer()?,
-            Some(_) => {
-                let lexeme = self.word()?;
-                Token::new(TokenTag::Word, lexeme)
+            Some('(') if matches!(self.next, Some('(')) => {
+                self.advance();
+                self.advance();
+                let lexeme = self.arithmetic("arithmetic command")?;
+                Token::new(TokenTag::Arithmetic, lexeme)
+            }
+            Some('(') => {
+                self.advance();
+                let lexeme = String::from("(");
+                Token::new(TokenTag::LeftParen, lexeme)
+            }
+            Some(')') => {
+                self.advance();
+                let lexeme = String::from(")");
+                Token::new(TokenTag::RightParen, lexeme)
             }
+            Some(c) if is_digit(c) => self.integer()?,
+            Some(_) => self.word(String::new())?,
         };
 
+        token.span = start..self.offset;
+        token.line = line;
         Ok(token)
     }
 }
@@ -147,22 +313,50 @@ impl<'a> parser_state::Lexer for Scanner<'a> {
 impl<'a> Scanner<'a> {
     /// Creates a scanner for a give command text.
     pub fn new<'b>(command_text: &'b str) -> Scanner<'b> {
+        Scanner::at_line(command_text, 1)
+    }
+
+    /// Creates a scanner for a command text which starts on the given line,
+    /// e.g. of a script.
+    pub fn at_line<'b>(command_text: &'b str, line: usize) -> Scanner<'b> {
         let mut scanner = Scanner {
+            text: command_text,
             chars: command_text.chars(),
             current: None,
             next: None,
+            offset: 0,
+            line,
+            is_extglob: false,
         };
         scanner.advance();
         scanner.advance();
         scanner
     }
 
-    /// Scans a quoted word.
-    fn word(&mut self) -> anyhow::Result<String> {
+    /// Makes the scanner scan extended pattern groups, e.g. `@(a|b)`, as parts
+    /// of words if `extglob` is on. Otherwise `(` ends a word.
+    pub fn with_extglob(mut self, is_extglob: bool) -> Scanner<'a> {
+        self.is_extglob = is_extglob;
+        self
+    }
+
+    /// Scans a quoted word. The word's text starts with the given prefix,
+    /// which has already been scanned.
+    fn word(&mut self, prefix: String) -> anyhow::Result<Token> {
         use WordState::*;
 
         let mut state = Normal;
-        let mut s = String::new();
+        let mut lexeme = prefix.clone();
+
+        // The finished parts of the word, parts inside double quotes that
+        // are being scanned, and text not yet added to a part.
+        let mut parts = Vec::new();
+        let mut quoted_parts = Vec::new();
+        let mut s = prefix;
+
+        // Number of extended pattern groups, e.g. `@(a|b)`, being scanned.
+        // Blanks and operators in them don't end the word.
+        let mut group_depth = 0;
 
         loop {
             match (self.current, state) {
@@ -179,10 +373,12 @@ impl<'a> Scanner<'a> {
                 }
 
                 (Some('\''), Normal) => {
+                    push_literal(&mut parts, &mut s);
                     state = InSingleQuote;
                 }
 
                 (Some('\''), InSingleQuote) => {
+                    parts.push(WordPart::Quoted(take(&mut s)));
                     state = Normal;
                 }
 
@@ -191,6 +387,7 @@ impl<'a> Scanner<'a> {
                 }
 
                 (Some('"'), Normal) => {
+                    push_literal(&mut parts, &mut s);
                     state = InDoubleQuote;
                 }
 
@@ -199,22 +396,73 @@ impl<'a> Scanner<'a> {
                 }
 
                 (Some('"'), InDoubleQuote) => {
+                    push_literal(&mut quoted_parts, &mut s);
+                    parts.push(WordPart::DoubleQuoted(take(&mut quoted_parts)));
                     state = Normal;
                 }
 
-                (Some(c), Normal) if is_whitespace(c) => {
+                (Some('$'), Normal) => {
+                    if let Some(part) = self.dollar(&mut lexeme)? {
+                        push_literal(&mut parts, &mut s);
+                        parts.push(part);
+                        continue;
+                    }
+                    s.push('$');
+                }
+
+                (Some('$'), InDoubleQuote) => {
+                    if let Some(part) = self.dollar(&mut lexeme)? {
+                        push_literal(&mut quoted_parts, &mut s);
+                        quoted_parts.push(part);
+                        continue;
+                    }
+                    s.push('$');
+                }
+
+                (Some('`'), Normal) => {
+                    push_literal(&mut parts, &mut s);
+                    parts.push(self.backquoted(&mut lexeme)?);
+                    continue;
+                }
+
+                (Some('`'), InDoubleQuote) => {
+                    push_literal(&mut quoted_parts, &mut s);
+                    quoted_parts.push(self.backquoted(&mut lexeme)?);
+                    continue;
+                }
+
+                (Some('('), Normal) if parts.is_empty() && is_array_assignment(&s) => {
+                    push_literal(&mut parts, &mut s);
+                    parts.push(self.array(&mut lexeme)?);
+                    continue;
+                }
+
+                (Some('('), Normal)
+                    if self.is_extglob && (group_depth > 0 || s.ends_with(is_group_operator)) =>
+                {
+                    group_depth += 1;
+                    s.push('(');
+                }
+
+                (Some(')'), Normal) if group_depth > 0 => {
+                    group_depth -= 1;
+                    s.push(')');
+                }
+
+                (Some(c), Normal) if group_depth == 0 && is_word_end(c) => {
                     break;
                 }
 
-                (Some(c), InSingleQuote) if is_whitespace(c) => {
-                    s.push(c);
+                // A backslash-newline continues the line.
+                (Some('\n'), BackSpace) => {
+                    state = Normal;
                 }
 
-                (Some(c), InDoubleQuote) if is_whitespace(c) => {
-                    s.push(c);
+                (Some('\n'), QuotedBackSpace) => {
+                    state = InDoubleQuote;
                 }
 
-                (Some(c), QuotedBackSpace) if c == '"' || c == '\\' => {
+                (Some(c), QuotedBackSpace) if matches!(c, '"' | '\\' | '$') => {
                     state = InDoubleQuote;
                     s.push(c);
                 }
@@ -227,31 +475,254 @@ impl<'a> Scanner<'a> {
 
                 (Some(c), BackSpace) => {
                     state = Normal;
-                    s.push(c);
+                    push_literal(&mut parts, &mut s);
+                    parts.push(WordPart::Quoted(c.to_string()));
                 }
 
                 (Some(c), _) => {
                     s.push(c);
                 }
 
+                (None, Normal) if group_depth > 0 => {
+                    Err(IncompleteError::new("unclosed pattern group", ")"))?
+                }
+
                 (None, Normal) => break,
 
-                (None, InSingleQuote) => Err(anyhow!("unclosed single quote"))?,
+                (None, InSingleQuote) => Err(IncompleteError::new("unclosed single quote", "'"))?,
 
                 (None, InDoubleQuote) | (None, QuotedBackSpace) => {
-                    Err(anyhow!("unclosed double quote"))?
+                    Err(IncompleteError::new("unclosed double quote", "\""))?
                 }
 
-                (None, BackSpace) => Err(anyhow!("dangling back space"))?,
+                (None, BackSpace) => Err(IncompleteError::new("dangling back space", ""))?,
             }
 
+            if let Some(c) = self.current {
+                lexeme.push(c);
+            }
             self.advance();
         }
 
-        Ok(s)
+        push_literal(&mut parts, &mut s);
+
+        if matches!(&parts[..], [WordPart::Literal(_)]) && self.function_parens() {
+            return Ok(Token::new(TokenTag::FunctionName, lexeme));
+        }
+
+        Ok(Token::word(lexeme, Word { parts }))
+    }
+
+    /// Scans past a `()`, which may contain blanks, if it comes next. Returns
+    /// true if it did.
+    fn function_parens(&mut self) -> bool {
+        let ahead = self
+            .current
+            .into_iter()
+            .chain(self.next)
+            .chain(self.chars.clone());
+
+        let mut is_open = false;
+        for (i, c) in ahead.enumerate() {
+            match c {
+                ' ' | '\t' => {}
+                '(' if !is_open => is_open = true,
+                ')' if is_open => {
+                    for _ in 0..=i {
+                        self.advance();
+                    }
+                    return true;
+                }
+                _ => return false,
+            }
+        }
+        false
+    }
+
+    /// Scans the elements of an array assignment, e.g. `(a b c)`, up to and
+    /// past the closing `)`.
+    fn array(&mut self, lexeme: &mut String) -> anyhow::Result<WordPart> {
+        assert!(self.current == Some('('));
+        self.advance();
+
+        let mut elements = Vec::new();
+        let mut lexemes = Vec::new();
+        loop {
+            let token = self.next_token()?;
+            match token.tag {
+                TokenTag::RightParen => break,
+                TokenTag::Newline => {}
+                TokenTag::Word => {
+                    lexemes.push(token.lexeme);
+                    elements.extend(token.word);
+                }
+                TokenTag::EndOfCommand => Err(IncompleteError::new("unclosed array", ")"))?,
+                tag => Err(anyhow!("syntax error near unexpected token `{tag}'"))?,
+            }
+        }
+
+        lexeme.push_str(&format!("({})", lexemes.join(" ")));
+        Ok(WordPart::Array(elements))
     }
 
-    /// Scans an integer token.
+    /// Scans an expansion starting at a `$` character. Returns `None`, having
+    /// scanned nothing, if the `$` doesn't start an expansion.
+    fn dollar(&mut self, lexeme: &mut String) -> anyhow::Result<Option<WordPart>> {
+        assert!(self.current == Some('$'));
+
+        let part = match self.next {
+            Some('(') if self.peek() == Some('(') => {
+                lexeme.push_str("$((");
+                self.advance();
+                self.advance();
+                self.advance();
+                let expression = self.arithmetic("arithmetic expansion")?;
+                lexeme.push_str(&expression);
+                lexeme.push_str("))");
+                WordPart::Arithmetic(expression)
+            }
+
+            Some('(') => {
+                self.advance();
+                self.advance();
+                let command_text = self.command_substitution()?;
+                lexeme.push_str(&format!("$({command_text})"));
+                WordPart::Command(command_text)
+            }
+
+            Some('{') => {
+                lexeme.push_str("${");
+                self.advance();
+                self.advance();
+
+                // Braces may nest, e.g. in `${arr[${i}]}`, and quoted or
+                // escaped braces don't count, e.g. in `${name:-"}"}`.
+                let mut text = String::new();
+                let mut depth = 0;
+                let mut quote = None;
+                let mut is_escaped = false;
+                loop {
+                    let Some(c) = self.current else {
+                        Err(IncompleteError::new("unclosed parameter expansion", "}"))?
+                    };
+                    match c {
+                        _ if is_escaped => is_escaped = false,
+                        '\\' if quote != Some('\'') => is_escaped = true,
+                        '\'' | '"' if quote == Some(c) => quote = None,
+                        '\'' | '"' if quote.is_none() => quote = Some(c),
+                        _ if quote.is_some() => {}
+                        '}' if depth == 0 => break,
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    text.push(c);
+                    self.advance();
+                }
+                self.advance();
+                lexeme.push_str(&text);
+                lexeme.push('}');
+                braced_parameter(&text)?
+            }
+
+            Some(c) if is_name_start(c) => {
+                lexeme.push('$');
+                self.advance();
+                let mut name = String::new();
+                while let Some(c) = self.current.filter(|&c| is_name_char(c)) {
+                    name.push(c);
+                    self.advance();
+                }
+                lexeme.push_str(&name);
+                WordPart::Parameter(name)
+            }
+
+            Some(c) if is_special_parameter(&c.to_string()) => {
+                lexeme.push('$');
+                lexeme.push(c);
+                self.advance();
+                self.advance();
+                WordPart::Parameter(c.to_string())
+            }
+
+            _ => return Ok(None),
+        };
+
+        Ok(Some(part))
+    }
+
+    /// Scans the commands of a `$(...)` command substitution up to and past
+    /// its closing `)`. They're parsed to find where they end, since they
+    /// may contain a `)` of their own, e.g. in a case item.
+    fn command_substitution(&mut self) -> anyhow::Result<String> {
+        let start = self.offset;
+        let len = parse_command_substitution(&self.text[start..], self.line, self.is_extglob)?;
+        while self.offset <= start + len {
+            self.advance();
+        }
+        Ok(self.text[start..start + len].to_string())
+    }
+
+    /// Scans the commands of a backquoted command substitution up to and past
+    /// its closing backquote. A backslash only quotes `$`, `` ` `` or `\`.
+    fn backquoted(&mut self, lexeme: &mut String) -> anyhow::Result<WordPart> {
+        assert!(self.current == Some('`'));
+        lexeme.push('`');
+        self.advance();
+
+        let mut command_text = String::new();
+        loop {
+            match self.current {
+                Some('`') => break,
+                Some('\\') if matches!(self.next, Some('$' | '`' | '\\')) => {
+                    lexeme.push('\\');
+                    self.advance();
+                }
+                Some(_) => {}
+                None => Err(IncompleteError::new("unclosed backquote", "`"))?,
+            }
+            if let Some(c) = self.current {
+                command_text.push(c);
+                lexeme.push(c);
+            }
+            self.advance();
+        }
+
+        lexeme.push('`');
+        self.advance();
+        Ok(WordPart::Command(command_text))
+    }
+
+    /// Scans the expression of an arithmetic command or expansion up to and
+    /// past its closing `))`.
+    fn arithmetic(&mut self, what: &str) -> anyhow::Result<String> {
+        let mut expression = String::new();
+        let mut depth = 0;
+        loop {
+            match self.current {
+                Some(')') if depth == 0 && matches!(self.next, Some(')')) => {
+                    self.advance();
+                    self.advance();
+                    break;
+                }
+                Some(')') if depth == 0 => Err(anyhow!("unmatched `)` in {what}"))?,
+                Some(c) => {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    expression.push(c);
+                    self.advance();
+                }
+                None => Err(IncompleteError::new(&format!("unclosed {what}"), ")"))?,
+            }
+        }
+        Ok(expression)
+    }
+
+    /// Scans a token starting with a digit, which is either a redirection
+    /// operator with a file descriptor or a word.
     fn integer(&mut self) -> anyhow::Result<Token> {
         let mut lexeme = String::new();
         loop {
@@ -264,41 +735,206 @@ impl<'a> Scanner<'a> {
             }
         }
 
-        let i = parse_u32(&lexeme)?;
-
-        let tag = match self.current {
+        let token = match self.current {
             Some('>') if matches!(self.next, Some('>')) => {
+                let i = parse_u32(&lexeme)?;
                 lexeme.push_str(">>");
                 self.advance();
                 self.advance();
-                TokenTag::RedirectOutAppendWithFileDescriptor(i)
+                Token::new(TokenTag::RedirectOutAppendWithFileDescriptor(i), lexeme)
             }
             Some('>') => {
+                let i = parse_u32(&lexeme)?;
                 lexeme.push('>');
                 self.advance();
-                TokenTag::RedirectOutWithFileDescriptor(i)
+                Token::new(TokenTag::RedirectOutWithFileDescriptor(i), lexeme)
             }
-            _ => TokenTag::Integer(i),
+            _ => self.word(lexeme)?,
         };
 
-        Ok(Token::new(tag, lexeme))
+        Ok(token)
     }
 
-    /// Advances the scanner past any whitespace.
+    /// Advances the scanner past any whitespace other than newlines, and past
+    /// backslash-newlines, which continue a line.
     fn skip_whitespace(&mut self) {
         loop {
             match self.current {
-                Some(c) if is_whitespace(c) => self.advance(),
+                Some(c) if is_whitespace(c) && c != '\n' => self.advance(),
+                Some('\\') if self.next == Some('\n') => {
+                    self.advance();
+                    self.advance();
+                }
                 _ => break,
             }
         }
     }
 
+    /// Advances past a comment up to the end of its line.
+    fn skip_comment(&mut self) {
+        while self.current.is_some_and(|c| c != '\n') {
+            self.advance();
+        }
+    }
+
     /// Advances `current` to the next character in command text.
     fn advance(&mut self) {
+        if let Some(c) = self.current {
+            self.offset += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+            }
+        }
         self.current = self.next;
         self.next = self.chars.next();
     }
+
+    /// Returns the character after `next` without advancing.
+    fn peek(&self) -> Option<char> {
+        self.chars.clone().next()
+    }
+}
+
+/// Parses the text between the braces of a parameter expansion, e.g.
+/// `#arr[@]` in `${#arr[@]}`.
+fn braced_parameter(text: &str) -> anyhow::Result<WordPart> {
+    let bad_substitution = || anyhow!("${{{text}}}: bad substitution");
+
+    let (mut operator, rest) = match text.strip_prefix('!') {
+        Some(rest) => (ParameterOperator::Indices, rest),
+        None => match text.strip_prefix('#') {
+            Some(rest) if !rest.is_empty() => (ParameterOperator::Length, rest),
+            _ => (ParameterOperator::Value, text),
+        },
+    };
+
+    let name_len = if rest.starts_with(is_name_start) {
+        rest.find(|c| !is_name_char(c)).unwrap_or(rest.len())
+    } else if rest.starts_with(|c: char| c.is_ascii_digit()) {
+        rest.find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len())
+    } else {
+        rest.chars()
+            .next()
+            .filter(|c| matches!(c, '?' | '#' | '@' | '*' | '$' | '!' | '-'))
+            .map_or(0, |c| c.len_utf8())
+    };
+    let (name, mut rest) = rest.split_at(name_len);
+    if name.is_empty() {
+        return Err(bad_substitution());
+    }
+
+    let mut subscript = None;
+    if let Some(inside) = rest.strip_prefix('[') {
+        if !is_name(name) {
+            return Err(bad_substitution());
+        }
+        let close = subscript_end(inside).ok_or_else(bad_substitution)?;
+        subscript = Some(match &inside[..close] {
+            "@" => Subscript::At,
+            "*" => Subscript::Star,
+            index => Subscript::Element(scan_subscript(index)?),
+        });
+        rest = &inside[close + 1..];
+    }
+
+    let (is_null_unset, default) = match rest.strip_prefix(':') {
+        Some(default) => (true, default),
+        None => (false, rest),
+    };
+    if let Some(c @ ('-' | '=' | '?' | '+')) = default.chars().next() {
+        if !matches!(operator, ParameterOperator::Value) {
+            return Err(bad_substitution());
+        }
+        operator = ParameterOperator::Default {
+            operator: c,
+            is_null_unset,
+            word: scan_subscript(&default[1..])?,
+        };
+        rest = "";
+    }
+
+    if let Some(slice) = rest.strip_prefix(':') {
+        if !matches!(operator, ParameterOperator::Value) {
+            return Err(bad_substitution());
+        }
+        operator = match slice.split_once(':') {
+            Some((offset, length)) => {
+                ParameterOperator::Slice(offset.to_string(), Some(length.to_string()))
+            }
+            None => ParameterOperator::Slice(slice.to_string(), None),
+        };
+        rest = "";
+    }
+
+    let is_all = matches!(subscript, Some(Subscript::At | Subscript::Star));
+    if !rest.is_empty() || (matches!(operator, ParameterOperator::Indices) && !is_all) {
+        return Err(bad_substitution());
+    }
+
+    if subscript.is_none() && matches!(operator, ParameterOperator::Value) {
+        return Ok(WordPart::Parameter(name.to_string()));
+    }
+
+    Ok(WordPart::Braced(Box::new(ParameterExpansion {
+        name: name.to_string(),
+        subscript,
+        operator,
+    })))
+}
+
+/// Finds the `]` which closes a subscript, allowing for nested brackets.
+fn subscript_end(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 0 => return Some(i),
+            ']' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Scans the text of an array subscript, e.g. `$i + 1`, or the word in a
+/// parameter expansion, e.g. `${name:-a b}`, into a single word. Blanks and
+/// operators in it are kept as literal text rather than ending the word.
+pub fn scan_subscript(text: &str) -> anyhow::Result<Word> {
+    let mut scanner = Scanner::new(text);
+    let mut parts = Vec::new();
+    let mut end = 0;
+    loop {
+        let token = scanner.next_token()?;
+        if token.tag == TokenTag::EndOfCommand {
+            break;
+        }
+        if token.span.start > end {
+            parts.push(WordPart::Literal(text[end..token.span.start].to_string()));
+        }
+        match token.word {
+            Some(word) => parts.extend(word.parts),
+            None => parts.push(WordPart::Literal(text[token.span.clone()].to_string())),
+        }
+        end = token.span.end;
+    }
+    if end < text.len() {
+        parts.push(WordPart::Literal(text[end..].to_string()));
+    }
+    Ok(Word { parts })
+}
+
+/// Determines if the text of a word so far makes it an array assignment when
+/// followed by `(`, e.g. `arr=`.
+fn is_array_assignment(s: &str) -> bool {
+    s.strip_suffix('=').is_some_and(is_name)
+}
+
+/// Adds any pending literal text to a list of word parts.
+fn push_literal(parts: &mut Vec<WordPart>, s: &mut String) {
+    if !s.is_empty() {
+        parts.push(WordPart::Literal(take(s)));
+    }
 }
 
 /// Parse string as an `u32` with a custom error result.
@@ -316,7 +952,41 @@ fn is_digit(c: char) -> bool {
     c.is_ascii_digit()
 }
 
+/// Determines if a character followed by `(` starts an extended pattern
+/// group, e.g. `+(a|b)`.
+fn is_group_operator(c: char) -> bool {
+    matches!(c, '?' | '*' | '+' | '@' | '!')
+}
+
 /// Determines if the given character is whitespace.
 fn is_whitespace(c: char) -> bool {
     c == ' ' || c == '\t' || c == '\r' || c == '\n'
 }
+
+/// Determines if an unquoted character ends a word.
+fn is_word_end(c: char) -> bool {
+    is_whitespace(c) || matches!(c, '|' | ';' | '<' | '>' | '(' | ')') || c == '&'
+}
+
+/// Determines if a character can start a variable name.
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+/// Determines if a character can be part of a variable name.
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Determines if a string is a valid variable name.
+pub fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if is_name_start(c)) && chars.all(is_name_char)
+}
+
+/// Determines if a string names a special parameter, e.g. `?`, or a
+/// positional parameter, e.g. `1`.
+fn is_special_parameter(s: &str) -> bool {
+    matches!(s, "?" | "#" | "@" | "*" | "$" | "!" | "-")
+        || (!s.is_empty() && s.chars().all(|c| c.is_ascii_digit()))
+}
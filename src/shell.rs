@@ -0,0 +1,570 @@
+//! State of the shell which persists between commands.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env::split_paths;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+
+use crate::arith;
+use crate::ast::Command;
+use crate::options::{Options, ShellOptions};
+use crate::variable::{Attributes, Value, Variable};
+
+/// A pending jump out of the normal flow of a list of commands.
+#[derive(Clone, Copy, Debug)]
+pub enum Jump {
+    /// Exits from the given number of enclosing loops.
+    Break(usize),
+
+    /// Exits from the given number of enclosing loops, less one, and resumes
+    /// the next iteration of the loop reached.
+    Continue(usize),
+
+    /// Returns from the function being evaluated.
+    Return,
+}
+
+/// The shell's state.
+pub struct Shell {
+    /// Exit status of the last command run.
+    pub status: i32,
+
+    /// The shell's or script's name, `$0`.
+    pub name: String,
+
+    /// Positional parameters, e.g. `$1`.
+    pub positional: Vec<String>,
+
+    /// Process ID of the shell, `$$`, which a subshell keeps.
+    pub pid: u32,
+
+    /// Exit status of the last command substitution in the command being
+    /// expanded, which is the status of a command with only assignments.
+    pub substitution_status: Option<i32>,
+
+    /// Process ID of the last command run in the background, `$!`.
+    pub background_pid: Option<u32>,
+
+    /// The shell reads commands from a terminal.
+    pub is_interactive: bool,
+
+    /// Where the shell reads commands from, for `$-`: `c` for a command
+    /// string, `s` for stdin, or none for a script.
+    pub input_flag: Option<char>,
+
+    /// Number of lines of commands read so far.
+    pub lines_read: usize,
+
+    /// Line number of the command being evaluated, `$LINENO`.
+    pub line_number: usize,
+
+    /// A `break`, `continue` or `return` waiting to be handled by an
+    /// enclosing loop or function.
+    pub jump: Option<Jump>,
+
+    /// Number of loops currently being evaluated.
+    pub loop_depth: usize,
+
+    /// Number of files currently being sourced.
+    pub source_depth: usize,
+
+    /// Number of commands being evaluated whose exit status is tested, e.g.
+    /// an `if` condition. `errexit` doesn't apply inside them.
+    pub condition_depth: usize,
+
+    /// Options changed with `set`.
+    pub options: Options,
+
+    /// Options changed with `shopt`.
+    pub shell_options: ShellOptions,
+
+    /// Alias values by name.
+    pub aliases: BTreeMap<String, String>,
+
+    /// The bodies of defined functions by name.
+    pub functions: HashMap<String, Rc<Command>>,
+
+    /// Shell variables by name.
+    variables: HashMap<String, Variable>,
+
+    /// For each function call being evaluated, the variables its locals
+    /// hid, which are restored when it returns.
+    local_frames: Vec<HashMap<String, Option<Variable>>>,
+
+    /// Names of variables whose values are computed when they're read, e.g.
+    /// `RANDOM`. Unsetting one makes it an ordinary variable.
+    dynamic: HashSet<&'static str>,
+
+    /// State of the generator for `$RANDOM`.
+    random_state: Cell<u32>,
+
+    /// When `$SECONDS` was last assigned, or the shell started.
+    seconds_start: Instant,
+
+    /// The value assigned to `$SECONDS`.
+    seconds_base: u64,
+
+    /// Seconds since the epoch when the shell started.
+    pub start_time: u64,
+}
+
+/// Variables whose values are computed when they're read.
+const DYNAMIC_VARIABLES: [&str; 5] = [
+    "EPOCHREALTIME",
+    "EPOCHSECONDS",
+    "LINENO",
+    "RANDOM",
+    "SECONDS",
+];
+
+impl Shell {
+    /// Creates a shell state with exported variables taken from the
+    /// environment.
+    pub fn new() -> Shell {
+        let mut variables: HashMap<String, Variable> = std::env::vars()
+            .map(|(name, value)| (name, Variable::exported(value)))
+            .collect();
+
+        // Each nested shell is a level deeper.
+        let level = variables
+            .get("SHLVL")
+            .and_then(Variable::scalar)
+            .and_then(|level| level.parse::<i64>().ok())
+            .unwrap_or(0);
+        let level = Variable::exported((level + 1).to_string());
+        variables.insert(String::from("SHLVL"), level);
+
+        let parent_pid = Variable {
+            value: Some(Value::Scalar(
+                std::os::unix::process::parent_id().to_string(),
+            )),
+            attributes: Attributes {
+                is_readonly: true,
+                ..Attributes::default()
+            },
+        };
+        variables.insert(String::from("PPID"), parent_pid);
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let seed = now.subsec_nanos() ^ std::process::id();
+
+        Shell {
+            status: 0,
+            name: std::env::args().next().unwrap_or_default(),
+            positional: Vec::new(),
+            pid: std::process::id(),
+            substitution_status: None,
+            background_pid: None,
+            is_interactive: true,
+            input_flag: None,
+            lines_read: 0,
+            line_number: 0,
+            jump: None,
+            loop_depth: 0,
+            source_depth: 0,
+            condition_depth: 0,
+            options: Options::default(),
+            shell_options: ShellOptions::default(),
+            aliases: BTreeMap::new(),
+            functions: HashMap::new(),
+            variables,
+            local_frames: Vec::new(),
+            dynamic: HashSet::from(DYNAMIC_VARIABLES),
+            random_state: Cell::new(seed),
+            seconds_start: Instant::now(),
+            seconds_base: 0,
+            start_time: now.as_secs(),
+        }
+    }
+
+    /// Gets the value of a variable or special parameter.
+    pub fn get_variable(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.status.to_string()),
+            "#" => Some(self.positional.len().to_string()),
+            "@" | "*" => Some(self.positional.join(" ")),
+            "$" => Some(self.pid.to_string()),
+            "!" => self.background_pid.map(|pid| pid.to_string()),
+            "-" => Some(self.flags()),
+            "0" => Some(self.name.clone()),
+            _ if name.starts_with(|c: char| c.is_ascii_digit()) => {
+                let i: usize = name.parse().ok()?;
+                self.positional.get(i.checked_sub(1)?).cloned()
+            }
+            _ if self.dynamic.contains(name) => Some(self.dynamic_value(name)),
+            _ => self.variables.get(name)?.scalar().map(String::from),
+        }
+    }
+
+    /// Computes the value of a dynamic variable.
+    fn dynamic_value(&self, name: &str) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        match name {
+            "EPOCHREALTIME" => format!("{}.{:06}", now.as_secs(), now.subsec_micros()),
+            "EPOCHSECONDS" => now.as_secs().to_string(),
+            "LINENO" => self.line_number.to_string(),
+            "RANDOM" => {
+                // A linear congruential generator, as in POSIX's example
+                // `rand`.
+                let state = self
+                    .random_state
+                    .get()
+                    .wrapping_mul(1103515245)
+                    .wrapping_add(12345);
+                self.random_state.set(state);
+                ((state >> 16) & 0x7fff).to_string()
+            }
+            "SECONDS" => {
+                let elapsed = self.seconds_start.elapsed().as_secs();
+                (self.seconds_base + elapsed).to_string()
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// Assigns a dynamic variable, which seeds `RANDOM` or sets the count of
+    /// `SECONDS`. Others can't be assigned.
+    fn assign_dynamic(&mut self, name: &str, value: &str) {
+        match name {
+            "RANDOM" => self.random_state.set(value.parse().unwrap_or(0)),
+            "SECONDS" => {
+                self.seconds_base = value.parse().unwrap_or(0);
+                self.seconds_start = Instant::now();
+            }
+            _ => {}
+        }
+    }
+
+    /// Gets the letters of the shell's options which are on, `$-`.
+    pub fn flags(&self) -> String {
+        // Like bash, commands are always hashed and braces always expanded,
+        // and the letters go in bash's order.
+        let mut flags: Vec<char> = self.options.letters().chars().collect();
+        flags.push('h');
+        if self.is_interactive {
+            flags.push('i');
+        }
+        flags.sort_unstable();
+        flags.push('B');
+        flags.extend(self.input_flag);
+        flags.into_iter().collect()
+    }
+
+    /// Gets the directories searched for commands and sourced files, from
+    /// `PATH` as it's set now.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        match self.get_variable("PATH") {
+            Some(path) => split_paths(&path).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Gets the characters which separate fields, from `IFS`, which are
+    /// blanks and newline if it's unset.
+    pub fn ifs(&self) -> String {
+        self.get_variable("IFS")
+            .unwrap_or_else(|| String::from(" \t\n"))
+    }
+
+    /// Determines if a variable exists, even if it's declared without a
+    /// value.
+    pub fn has_variable(&self, name: &str) -> bool {
+        self.variables.contains_key(name) || self.dynamic.contains(name)
+    }
+
+    /// Gets a variable, including its attributes.
+    pub fn variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.get(name)
+    }
+
+    /// Gets a variable to change its attributes, declaring it if it doesn't
+    /// exist.
+    pub fn declare_variable(&mut self, name: &str) -> &mut Variable {
+        self.variables.entry(name.to_string()).or_default()
+    }
+
+    /// Gets all variables, sorted by name.
+    pub fn variables(&self) -> Vec<(&str, &Variable)> {
+        let mut variables: Vec<(&str, &Variable)> = self
+            .variables
+            .iter()
+            .map(|(name, variable)| (name.as_str(), variable))
+            .collect();
+        variables.sort_by_key(|(name, _)| *name);
+        variables
+    }
+
+    /// Gets the names and values of exported variables, which are the
+    /// environment of child processes.
+    pub fn exported_variables(&self) -> Vec<(String, String)> {
+        self.variables
+            .iter()
+            .filter(|(_, variable)| variable.attributes.is_exported)
+            .filter_map(|(name, variable)| Some((name.clone(), variable.scalar()?.to_string())))
+            .collect()
+    }
+
+    /// Sets the value of a variable, converting it as the variable's
+    /// attributes require. Setting an array sets its element zero.
+    pub fn set_variable(&mut self, name: &str, value: String) -> anyhow::Result<()> {
+        let value = self.convert_value(name, value)?;
+        if self.dynamic.contains(name) {
+            self.assign_dynamic(name, &value);
+            return Ok(());
+        }
+        let variable = self.variables.entry(name.to_string()).or_default();
+        match &mut variable.value {
+            Some(Value::Indexed(items)) => {
+                items.insert(0, value);
+            }
+            Some(Value::Associative(items)) => {
+                items.insert(String::from("0"), value);
+            }
+            other => *other = Some(Value::Scalar(value)),
+        }
+        Ok(())
+    }
+
+    /// Gets an element of an array by its index or key. Element zero of a
+    /// scalar is its value. Fails if a negative index is before the start of
+    /// the array.
+    pub fn element(&mut self, name: &str, key: &str) -> anyhow::Result<Option<String>> {
+        if let Some(Value::Associative(items)) = self.value(name) {
+            return Ok(items.get(key).cloned());
+        }
+        let index = self
+            .index(name, key)?
+            .ok_or_else(|| anyhow!("{name}: bad array subscript"))?;
+        let element = match self.value(name) {
+            Some(Value::Indexed(items)) => items.get(&index).cloned(),
+            Some(Value::Scalar(value)) if index == 0 => Some(value.clone()),
+            _ => None,
+        };
+        Ok(element)
+    }
+
+    /// Sets an element of an array by its index or key. A scalar or unset
+    /// variable becomes an indexed array.
+    pub fn set_element(&mut self, name: &str, key: &str, value: String) -> anyhow::Result<()> {
+        let value = self.convert_value(name, value)?;
+        if let Some(Value::Associative(items)) = self.value_mut(name) {
+            items.insert(key.to_string(), value);
+            return Ok(());
+        }
+
+        let index = self
+            .index(name, key)?
+            .ok_or_else(|| anyhow!("{name}[{key}]: bad array subscript"))?;
+        let variable = self.variables.entry(name.to_string()).or_default();
+        let mut items = match variable.value.take() {
+            Some(Value::Indexed(items)) => items,
+            Some(Value::Scalar(scalar)) => BTreeMap::from([(0, scalar)]),
+            _ => BTreeMap::new(),
+        };
+        items.insert(index, value);
+        variable.value = Some(Value::Indexed(items));
+        Ok(())
+    }
+
+    /// Replaces a variable's value with an array of elements, each with an
+    /// optional index or key. Elements without an index follow the one
+    /// before. An associative array stays associative, and its elements
+    /// must all have keys.
+    pub fn set_array(
+        &mut self,
+        name: &str,
+        elements: Vec<(Option<String>, String)>,
+    ) -> anyhow::Result<()> {
+        let is_associative = matches!(self.value(name), Some(Value::Associative(_)));
+        let mut indexed = BTreeMap::new();
+        let mut associative = BTreeMap::new();
+        let mut next = 0;
+        for (key, value) in elements {
+            let value = self.convert_value(name, value)?;
+            match key {
+                Some(key) if is_associative => {
+                    associative.insert(key, value);
+                }
+                None if is_associative => {
+                    return Err(anyhow!(
+                        "{name}: {value}: must use subscript when assigning associative array"
+                    ));
+                }
+                Some(key) => {
+                    let index = arith::evaluate(self, &key)?;
+                    next = usize::try_from(index)
+                        .map_err(|_| anyhow!("{name}[{key}]: bad array subscript"))?;
+                    indexed.insert(next, value);
+                    next += 1;
+                }
+                None => {
+                    indexed.insert(next, value);
+                    next += 1;
+                }
+            }
+        }
+
+        let variable = self.variables.entry(name.to_string()).or_default();
+        variable.value = Some(if is_associative {
+            Value::Associative(associative)
+        } else {
+            Value::Indexed(indexed)
+        });
+        Ok(())
+    }
+
+    /// Removes an element of an array. Fails if the array is readonly.
+    pub fn unset_element(&mut self, name: &str, key: &str) -> anyhow::Result<()> {
+        if self
+            .variables
+            .get(name)
+            .is_some_and(|variable| variable.attributes.is_readonly)
+        {
+            return Err(anyhow!("{name}: cannot unset: readonly variable"));
+        }
+        if let Some(Value::Associative(items)) = self.value_mut(name) {
+            items.remove(key);
+            return Ok(());
+        }
+        let index = self.index(name, key)?;
+        match self.value_mut(name) {
+            Some(Value::Indexed(items)) => {
+                if let Some(index) = index {
+                    items.remove(&index);
+                }
+            }
+            Some(Value::Scalar(_)) if index == Some(0) => {
+                self.variables.remove(name);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Evaluates the index of an indexed array's element. A negative index
+    /// counts back from the end of the array. Returns `None` if it's before
+    /// the start.
+    fn index(&mut self, name: &str, key: &str) -> anyhow::Result<Option<usize>> {
+        let index = arith::evaluate(self, key)?;
+        if index >= 0 {
+            return Ok(usize::try_from(index).ok());
+        }
+        let len = match self.value(name) {
+            Some(Value::Indexed(items)) => items.keys().next_back().map_or(0, |last| last + 1),
+            Some(Value::Scalar(_)) => 1,
+            _ => 0,
+        };
+        Ok(usize::try_from(len as i64 + index).ok())
+    }
+
+    fn value(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)?.value.as_ref()
+    }
+
+    fn value_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.variables.get_mut(name)?.value.as_mut()
+    }
+
+    /// Converts a value being assigned to a variable as its attributes
+    /// require. Fails if the variable is readonly.
+    fn convert_value(&mut self, name: &str, value: String) -> anyhow::Result<String> {
+        let attributes = self
+            .variables
+            .get(name)
+            .map(|variable| variable.attributes)
+            .unwrap_or_default();
+
+        if attributes.is_readonly {
+            return Err(anyhow!("{name}: readonly variable"));
+        }
+
+        let value = if attributes.is_integer {
+            arith::evaluate(self, &value)?.to_string()
+        } else {
+            value
+        };
+
+        if attributes.is_lowercase {
+            Ok(value.to_lowercase())
+        } else if attributes.is_uppercase {
+            Ok(value.to_uppercase())
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Removes a variable. Fails if it's readonly.
+    pub fn unset_variable(&mut self, name: &str) -> anyhow::Result<()> {
+        if self
+            .variables
+            .get(name)
+            .is_some_and(|variable| variable.attributes.is_readonly)
+        {
+            return Err(anyhow!("{name}: cannot unset: readonly variable"));
+        }
+        self.variables.remove(name);
+        self.dynamic.remove(name);
+        Ok(())
+    }
+
+    /// Takes a copy of a variable so it can be restored later.
+    pub fn save_variable(&self, name: &str) -> Option<Variable> {
+        self.variables.get(name).cloned()
+    }
+
+    /// Restores a variable saved with `save_variable`, regardless of its
+    /// attributes.
+    pub fn restore_variable(&mut self, name: &str, variable: Option<Variable>) {
+        match variable {
+            Some(variable) => self.variables.insert(name.to_string(), variable),
+            None => self.variables.remove(name),
+        };
+    }
+
+    /// Determines if a function call is being evaluated.
+    pub fn is_in_function(&self) -> bool {
+        !self.local_frames.is_empty()
+    }
+
+    /// Starts a function call, which can then have local variables.
+    pub fn push_local_frame(&mut self) {
+        self.local_frames.push(HashMap::new());
+    }
+
+    /// Ends a function call, restoring the variables its locals hid.
+    pub fn pop_local_frame(&mut self) {
+        let Some(frame) = self.local_frames.pop() else {
+            return;
+        };
+        for (name, variable) in frame {
+            self.restore_variable(&name, variable);
+        }
+    }
+
+    /// Makes a variable local to the function call being evaluated. It
+    /// starts out unset, and functions it calls see its local value.
+    pub fn make_local(&mut self, name: &str) -> anyhow::Result<()> {
+        if self
+            .variables
+            .get(name)
+            .is_some_and(|variable| variable.attributes.is_readonly)
+        {
+            return Err(anyhow!("{name}: readonly variable"));
+        }
+        let Some(frame) = self.local_frames.last_mut() else {
+            return Err(anyhow!("local: can only be used in a function"));
+        };
+        if !frame.contains_key(name) {
+            frame.insert(name.to_string(), self.variables.remove(name));
+        }
+        Ok(())
+    }
+}
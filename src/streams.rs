@@ -0,0 +1,233 @@
+//! Standard streams of commands being evaluated.
+//!
+//! Built-ins read and write these streams directly. External commands get them as
+//! the child process's stdio.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::process::{Child, ChildStdin, Stdio};
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Where a command reads its input from.
+pub enum Input {
+    /// The shell's own stdin.
+    Inherit,
+
+    /// A file or pipe.
+    File(File),
+
+    /// Output buffered from a built-in command.
+    Buffer(Rc<RefCell<Cursor<Vec<u8>>>>),
+}
+
+impl Input {
+    pub fn try_clone(&self) -> io::Result<Input> {
+        let input = match self {
+            Self::Inherit => Self::Inherit,
+            Self::File(file) => Self::File(file.try_clone()?),
+            Self::Buffer(buffer) => Self::Buffer(Rc::clone(buffer)),
+        };
+        Ok(input)
+    }
+
+    /// Creates stdio for a child process. A buffer is written to the child
+    /// with `feed_child` after it's spawned.
+    pub fn to_stdio(&self) -> io::Result<Stdio> {
+        let stdio = match self {
+            Self::Inherit => Stdio::inherit(),
+            Self::File(file) => Stdio::from(file.try_clone()?),
+            Self::Buffer(_) => Stdio::piped(),
+        };
+        Ok(stdio)
+    }
+
+    /// Writes the unread contents of a buffer to a spawned child's stdin on
+    /// another thread, so a child which doesn't read its input can't block
+    /// the shell.
+    pub fn feed_child(&self, child: &mut Child) {
+        if let (Self::Buffer(_), Some(stdin)) = (self, child.stdin.take()) {
+            let bytes = self.read_buffer();
+            std::thread::spawn(move || write_all_and_close(stdin, bytes));
+        }
+    }
+
+    /// Reads a byte, e.g. for the `read` builtin. Nothing past it is read, so
+    /// the rest of the input is left for later commands. Returns `None` at
+    /// the end of the input.
+    pub fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        loop {
+            let result = match self {
+                // Reading stdin directly avoids `io::stdin`'s buffer, which
+                // would read ahead.
+                Self::Inherit => {
+                    match unsafe { libc::read(libc::STDIN_FILENO, byte.as_mut_ptr().cast(), 1) } {
+                        -1 => Err(io::Error::last_os_error()),
+                        n => Ok(n as usize),
+                    }
+                }
+                Self::File(file) => file.read(&mut byte),
+                Self::Buffer(buffer) => buffer.borrow_mut().read(&mut byte),
+            };
+            match result {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Waits until there's input to read, or the timeout passes. Returns
+    /// false if it timed out. A buffer is always ready.
+    pub fn wait(&self, timeout: Duration) -> io::Result<bool> {
+        let Some(fd) = self.fd() else {
+            return Ok(true);
+        };
+        let mut poll_fd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        match unsafe { libc::poll(&mut poll_fd, 1, timeout) } {
+            -1 => Err(io::Error::last_os_error()),
+            n => Ok(n > 0),
+        }
+    }
+
+    /// Gets the file descriptor input is read from, which a buffer doesn't
+    /// have.
+    pub fn fd(&self) -> Option<RawFd> {
+        match self {
+            Self::Inherit => Some(libc::STDIN_FILENO),
+            Self::File(file) => Some(file.as_raw_fd()),
+            Self::Buffer(_) => None,
+        }
+    }
+
+    /// Reads the unread contents of a buffer, which is empty for other
+    /// inputs.
+    pub fn read_buffer(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        if let Self::Buffer(buffer) = self {
+            let _ = buffer.borrow_mut().read_to_end(&mut bytes);
+        }
+        bytes
+    }
+}
+
+fn write_all_and_close(mut stdin: ChildStdin, bytes: Vec<u8>) {
+    let _ = stdin.write_all(&bytes);
+}
+
+/// Where a command writes output to.
+pub enum Output {
+    /// The shell's own stdout.
+    Stdout,
+
+    /// The shell's own stderr.
+    Stderr,
+
+    /// A file or pipe.
+    File(File),
+
+    /// A buffer which is read after the command finishes.
+    Buffer(Rc<RefCell<Vec<u8>>>),
+}
+
+impl Output {
+    /// Creates an empty buffer output.
+    pub fn buffer() -> Output {
+        Self::Buffer(Rc::new(RefCell::new(Vec::new())))
+    }
+
+    pub fn try_clone(&self) -> io::Result<Output> {
+        let output = match self {
+            Self::Stdout => Self::Stdout,
+            Self::Stderr => Self::Stderr,
+            Self::File(file) => Self::File(file.try_clone()?),
+            Self::Buffer(buffer) => Self::Buffer(Rc::clone(buffer)),
+        };
+        Ok(output)
+    }
+
+    /// Creates stdio for a child process. A buffer's contents are read from
+    /// the child with `collect_child` after it's spawned.
+    pub fn to_stdio(&self) -> io::Result<Stdio> {
+        let stdio = match self {
+            Self::Stdout => Stdio::inherit(),
+            Self::Stderr => Stdio::from(io::stderr().as_fd().try_clone_to_owned()?),
+            Self::File(file) => Stdio::from(file.try_clone()?),
+            Self::Buffer(_) => Stdio::piped(),
+        };
+        Ok(stdio)
+    }
+
+    /// Reads a spawned child's stdout into a buffer until the child closes it.
+    pub fn collect_child(&self, child: &mut Child) -> io::Result<()> {
+        if let (Self::Buffer(buffer), Some(mut stdout)) = (self, child.stdout.take()) {
+            let mut bytes = Vec::new();
+            stdout.read_to_end(&mut bytes)?;
+            buffer.borrow_mut().extend(bytes);
+        }
+        Ok(())
+    }
+
+    /// Takes the contents of a buffer, which is empty for other outputs.
+    pub fn take_buffer(&self) -> Vec<u8> {
+        match self {
+            Self::Buffer(buffer) => buffer.take(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Stdout => io::stdout().write(buf),
+            Self::Stderr => io::stderr().write(buf),
+            Self::File(file) => file.write(buf),
+            Self::Buffer(buffer) => buffer.borrow_mut().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Stdout => io::stdout().flush(),
+            Self::Stderr => io::stderr().flush(),
+            Self::File(file) => file.flush(),
+            Self::Buffer(_) => Ok(()),
+        }
+    }
+}
+
+/// The standard streams of a command.
+pub struct Streams {
+    pub stdin: Input,
+    pub stdout: Output,
+    pub stderr: Output,
+}
+
+impl Streams {
+    /// Creates the shell's own standard streams.
+    pub fn standard() -> Streams {
+        Streams {
+            stdin: Input::Inherit,
+            stdout: Output::Stdout,
+            stderr: Output::Stderr,
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<Streams> {
+        Ok(Streams {
+            stdin: self.stdin.try_clone()?,
+            stdout: self.stdout.try_clone()?,
+            stderr: self.stderr.try_clone()?,
+        })
+    }
+}
@@ -1,5 +1,9 @@
 //! Contains all code dealing with system access.
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{FromRawFd, OwnedFd};
 use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::ExitStatusExt;
 use std::process::Child;
 use std::{io::ErrorKind, process::Command};
 
@@ -7,12 +11,22 @@ use anyhow::anyhow;
 use std::{
     env::{split_paths, var_os},
     fs::{read_dir, DirEntry},
-    path::PathBuf,
+    path::{Component, Path, PathBuf},
 };
 use trie_rs::TrieBuilder;
 
 use crate::error::EvalError;
 
+/// Describes an I/O error the way other shells do, e.g. `Permission
+/// denied`, without the `(os error 13)` the standard library adds.
+pub fn error_message(error: &io::Error) -> String {
+    let message = error.to_string();
+    match message.rfind(" (os error ") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+    }
+}
+
 /// Changes the current directory.
 pub fn change_directory(path: &PathBuf) -> anyhow::Result<()> {
     match std::env::set_current_dir(path) {
@@ -26,6 +40,65 @@ pub fn change_directory(path: &PathBuf) -> anyhow::Result<()> {
     }
 }
 
+/// Corrects small spelling mistakes in the names of a directory's path, as
+/// `cdspell` does. Each name may have one character transposed, missing,
+/// extra or wrong. Returns `None` if the path can't be corrected.
+pub fn correct_directory_spelling(path: &str) -> Option<PathBuf> {
+    let mut corrected = PathBuf::new();
+    for component in Path::new(path).components() {
+        let Component::Normal(name) = component else {
+            corrected.push(component);
+            continue;
+        };
+        if corrected.join(name).is_dir() {
+            corrected.push(name);
+            continue;
+        }
+
+        let directory = match corrected.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => corrected.as_path(),
+        };
+        let name = name.to_str()?;
+        let mut names: Vec<String> = read_dir(directory)
+            .ok()?
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|entry| is_misspelling(name, entry))
+            .collect();
+        names.sort();
+        let name = names
+            .into_iter()
+            .find(|name| corrected.join(name).is_dir())?;
+        corrected.push(name);
+    }
+    Some(corrected)
+}
+
+/// Determines if a name is another name with one character transposed,
+/// missing, extra or wrong.
+fn is_misspelling(name: &str, other: &str) -> bool {
+    let a: Vec<char> = name.chars().collect();
+    let b: Vec<char> = other.chars().collect();
+    let is_one_extra =
+        |a: &[char], b: &[char]| (0..a.len()).any(|i| a[..i] == b[..i] && a[i + 1..] == b[i..]);
+
+    if a.len() == b.len() {
+        let differences: Vec<usize> = (0..a.len()).filter(|&i| a[i] != b[i]).collect();
+        match differences[..] {
+            [_] => true,
+            [i, j] => j == i + 1 && a[i] == b[j] && a[j] == b[i],
+            _ => false,
+        }
+    } else if a.len() == b.len() + 1 {
+        is_one_extra(&a, &b)
+    } else if a.len() + 1 == b.len() {
+        is_one_extra(&b, &a)
+    } else {
+        false
+    }
+}
+
 /// Gets a vector of all paths in the PATH environment variable.
 pub fn get_path() -> Vec<PathBuf> {
     match var_os("PATH") {
@@ -57,22 +130,32 @@ pub fn search_for_executable_file(paths: &[PathBuf], file_name: &str) -> Option<
     None
 }
 
+/// Searches for a file in a collection of paths, e.g. one to be sourced.
+/// Unlike commands, the file doesn't need to be executable.
+pub fn search_for_file(paths: &[PathBuf], file_name: &str) -> Option<PathBuf> {
+    paths
+        .iter()
+        .map(|path| path.join(file_name))
+        .find(|path| path.is_file())
+}
+
 /// Spawn a command and return the child process handle. This handles errors
 /// in a way required by the challenge spec.
 pub fn spawn_command(command: &mut Command) -> anyhow::Result<Child> {
     match command.spawn() {
         Ok(child) => Ok(child),
         Err(e) => {
-            let message = match e.kind() {
+            let (message, status) = match e.kind() {
                 ErrorKind::NotFound => {
-                    format!(
+                    let message = format!(
                         "{}: command not found",
                         command.get_program().to_string_lossy()
-                    )
+                    );
+                    (message, 127)
                 }
-                _ => format!("{}", e),
+                _ => (format!("{}", e), 126),
             };
-            Err(EvalError::new(message))?
+            Err(EvalError::with_status(message, status))?
         }
     }
 }
@@ -100,3 +183,71 @@ pub fn trie_builder_with_path_executables(paths: &[PathBuf]) -> TrieBuilder<u8>
 
     builder
 }
+
+/// Waits for a child process to exit. Returns its exit status, which is 128
+/// plus the signal number if it was killed by a signal.
+pub fn wait_for_child(child: &mut Child) -> anyhow::Result<i32> {
+    let status = child.wait()?;
+    let code = status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0));
+    Ok(code)
+}
+
+/// Which side of a fork the calling process is on.
+pub enum Fork {
+    /// The new child process.
+    Child,
+
+    /// The original process, given the child's process id.
+    Parent(libc::pid_t),
+}
+
+/// Forks the shell into a child process, e.g. to evaluate a subshell.
+pub fn fork() -> anyhow::Result<Fork> {
+    // Anything buffered would otherwise be written by both processes.
+    io::stdout().flush()?;
+
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error())?,
+        0 => {
+            // The shell ignores `SIGPIPE`, but a child writing to a pipe
+            // nobody reads should be killed by it, like any command.
+            unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+            Ok(Fork::Child)
+        }
+        pid => Ok(Fork::Parent(pid)),
+    }
+}
+
+/// Waits for a forked child process to exit. Returns its exit status, which
+/// is 128 plus the signal number if it was killed by a signal.
+pub fn wait_for_pid(pid: libc::pid_t) -> anyhow::Result<i32> {
+    let mut status = 0;
+    while unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+        let e = io::Error::last_os_error();
+        if e.kind() != ErrorKind::Interrupted {
+            Err(e)?
+        }
+    }
+
+    if libc::WIFSIGNALED(status) {
+        Ok(128 + libc::WTERMSIG(status))
+    } else {
+        Ok(libc::WEXITSTATUS(status))
+    }
+}
+
+/// Creates a pipe. Returns its read and write ends, which aren't inherited
+/// by spawned commands.
+pub fn pipe() -> io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    for fd in fds {
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    let [reader, writer] = fds.map(|fd| File::from(unsafe { OwnedFd::from_raw_fd(fd) }));
+    Ok((reader, writer))
+}
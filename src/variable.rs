@@ -0,0 +1,156 @@
+//! Shell variables, which are kept separately from the process environment.
+
+use std::collections::BTreeMap;
+
+/// A shell variable.
+#[derive(Clone, Default)]
+pub struct Variable {
+    /// The variable's value, or `None` if it's declared but unset, e.g. by
+    /// `declare -i name`.
+    pub value: Option<Value>,
+
+    pub attributes: Attributes,
+}
+
+/// The value of a variable.
+#[derive(Clone)]
+pub enum Value {
+    Scalar(String),
+
+    /// An indexed array, which may have gaps between its indices.
+    Indexed(BTreeMap<usize, String>),
+
+    /// An associative array.
+    Associative(BTreeMap<String, String>),
+}
+
+/// Attributes of a variable, which are set by `declare` and its relatives.
+#[derive(Clone, Copy, Default)]
+pub struct Attributes {
+    /// The variable is passed to child processes.
+    pub is_exported: bool,
+
+    /// The variable can't be assigned or unset.
+    pub is_readonly: bool,
+
+    /// Values assigned to the variable are evaluated as arithmetic.
+    pub is_integer: bool,
+
+    /// Values assigned to the variable are converted to lower case.
+    pub is_lowercase: bool,
+
+    /// Values assigned to the variable are converted to upper case.
+    pub is_uppercase: bool,
+}
+
+impl Variable {
+    /// Creates an exported variable, e.g. for one from the environment.
+    pub fn exported(value: String) -> Variable {
+        Variable {
+            value: Some(Value::Scalar(value)),
+            attributes: Attributes {
+                is_exported: true,
+                ..Attributes::default()
+            },
+        }
+    }
+
+    /// Gets the value the variable expands to without a subscript, which
+    /// is element zero of an array.
+    pub fn scalar(&self) -> Option<&str> {
+        match self.value.as_ref()? {
+            Value::Scalar(value) => Some(value),
+            Value::Indexed(items) => items.get(&0).map(String::as_str),
+            Value::Associative(items) => items.get("0").map(String::as_str),
+        }
+    }
+
+    /// Gets the values of the variable's elements in order of their indices
+    /// or keys. A scalar is a single element.
+    pub fn values(&self) -> Vec<&str> {
+        match &self.value {
+            None => Vec::new(),
+            Some(Value::Scalar(value)) => vec![value],
+            Some(Value::Indexed(items)) => items.values().map(String::as_str).collect(),
+            Some(Value::Associative(items)) => items.values().map(String::as_str).collect(),
+        }
+    }
+
+    /// Gets the indices or keys of the variable's elements.
+    pub fn keys(&self) -> Vec<String> {
+        match &self.value {
+            None => Vec::new(),
+            Some(Value::Scalar(_)) => vec![String::from("0")],
+            Some(Value::Indexed(items)) => items.keys().map(usize::to_string).collect(),
+            Some(Value::Associative(items)) => items.keys().cloned().collect(),
+        }
+    }
+
+    /// Formats the variable as a `declare` command which recreates it, as
+    /// `declare -p` prints it.
+    pub fn declaration(&self, name: &str) -> String {
+        let mut flags = String::new();
+        match self.value {
+            Some(Value::Indexed(_)) => flags.push('a'),
+            Some(Value::Associative(_)) => flags.push('A'),
+            _ => {}
+        }
+        let attributes = [
+            (self.attributes.is_integer, 'i'),
+            (self.attributes.is_lowercase, 'l'),
+            (self.attributes.is_readonly, 'r'),
+            (self.attributes.is_uppercase, 'u'),
+            (self.attributes.is_exported, 'x'),
+        ];
+        for (is_set, flag) in attributes {
+            if is_set {
+                flags.push(flag);
+            }
+        }
+        if flags.is_empty() {
+            flags.push('-');
+        }
+
+        match self.quoted_value() {
+            Some(value) => format!("declare -{flags} {name}={value}"),
+            None => format!("declare -{flags} {name}"),
+        }
+    }
+
+    /// Formats the variable's value so it can be read back in as the value of
+    /// an assignment, e.g. `"a b"` or `([0]="a" [1]="b")`.
+    pub fn quoted_value(&self) -> Option<String> {
+        let value = match self.value.as_ref()? {
+            Value::Scalar(value) => double_quote(value),
+            Value::Indexed(items) => {
+                let items: Vec<String> = items
+                    .iter()
+                    .map(|(index, item)| format!("[{index}]={}", double_quote(item)))
+                    .collect();
+                format!("({})", items.join(" "))
+            }
+            Value::Associative(items) => {
+                let items: Vec<String> = items
+                    .iter()
+                    .map(|(key, item)| format!("[{key}]={}", double_quote(item)))
+                    .collect();
+                format!("({})", items.join(" "))
+            }
+        };
+        Some(value)
+    }
+}
+
+/// Quotes text with double quotes, escaping the characters which are still
+/// special inside them.
+fn double_quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,159 @@
+//! Checks of the shell's behavior which need it to run as a separate
+//! process, e.g. reading a script piped to its stdin.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Runs the shell with the given arguments and stdin. Returns its stdout and
+/// stderr.
+fn run(args: &[&str], stdin: &str) -> (String, String) {
+    run_command(shell().args(args), stdin)
+}
+
+/// Creates a command which runs the shell.
+fn shell() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+}
+
+/// Runs a command with the given stdin. Returns its stdout and stderr.
+fn run_command(command: &mut Command, stdin: &str) -> (String, String) {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the shell should start");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn read_from_piped_script() {
+    let (stdout, stderr) = run(&[], "read x\nhello\necho \"x=$x\"\n");
+    assert_eq!(stdout, "x=hello\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn command_substitution() {
+    let script = "x=$(false); echo $?\necho $(echo a  b) \"$(printf 'c\\n\\n')\" `echo d`\n";
+    let (stdout, stderr) = run(&[], script);
+    assert_eq!(stdout, "1\na b c d\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn login_shell_sources_system_profile() {
+    // An empty home directory leaves only `/etc/profile`, if there is one.
+    let home = Path::new(env!("CARGO_TARGET_TMPDIR")).join("empty-home");
+    fs::create_dir_all(&home).unwrap();
+    let (stdout, stderr) = run_command(shell().arg("-l").env("HOME", &home), "echo ok\n");
+    assert_eq!(stdout, "ok\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn pattern_groups_need_extglob() {
+    // Without `extglob`, `(` after a pattern operator is a syntax error, as
+    // it was before extended patterns were supported.
+    let (stdout, stderr) = run(&[], "echo @(a)\n");
+    assert_eq!(stdout, "");
+    assert_eq!(stderr, "syntax error near unexpected token `(`\n");
+
+    let script = "shopt -s extglob\ncase ab in +(a|b)) echo match;; esac\n";
+    let (stdout, stderr) = run(&[], script);
+    assert_eq!(stdout, "match\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn quoted_pattern_groups_match_themselves() {
+    let script = concat!(
+        "shopt -s extglob\n",
+        "case a in \"@(a)\") echo double;; '@(a)') echo single;; *) echo none;; esac\n",
+        "x='@(a|b)'; [[ b == \"$x\" ]] && echo quoted; [[ b == $x ]] && echo unquoted\n",
+    );
+    let (stdout, stderr) = run(&[], script);
+    assert_eq!(stdout, "none\nunquoted\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn built_in_arguments_are_expanded_before_assignments() {
+    let (stdout, stderr) = run(&["-c", "X=tmp echo \"$X\""], "");
+    assert_eq!(stdout, "\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn aliases_expand_only_in_command_positions() {
+    let script = concat!(
+        "alias ll='echo L'\n",
+        "case ll in (ll) echo matched;; esac\n",
+        "case x in (a|ll) ;; (*) ll body;; esac\n",
+        "X=1 ll assigned\n",
+    );
+    let (stdout, stderr) = run(&[], script);
+    assert_eq!(stdout, "matched\nL body\nL assigned\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn sourced_files_are_searched_for_in_current_path() {
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("sourced-path");
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("lib.sh"), "echo sourced\n").unwrap();
+    let script = format!("PATH={}:$PATH; . lib.sh\n", directory.display());
+    let (stdout, stderr) = run(&[], &script);
+    assert_eq!(stdout, "sourced\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn printf_rejects_huge_field_widths() {
+    let script = "printf '%99999999999d' 1; echo $?\nprintf '%.*d' 99999999999 1; echo $?\n";
+    let (stdout, stderr) = run(&[], script);
+    assert_eq!(stdout, "1\n1\n");
+    assert_eq!(
+        stderr,
+        "printf: invalid field width\nprintf: invalid precision\n"
+    );
+}
+
+#[test]
+fn flags_include_the_shell_mode() {
+    let (stdout, stderr) = run(&["-c", "echo $-; set -u; echo $-"], "");
+    assert_eq!(stdout, "hBc\nhuBc\n");
+    assert_eq!(stderr, "");
+    let (stdout, _) = run(&[], "echo $-\n");
+    assert_eq!(stdout, "hBs\n");
+}
+
+#[test]
+fn redirection_errors_omit_os_error_codes() {
+    let (stdout, stderr) = run(&["-c", "echo x > /; cat < /nonexistent; echo $?"], "");
+    assert_eq!(stdout, "1\n");
+    assert_eq!(
+        stderr,
+        "/: Is a directory\n/nonexistent: No such file or directory\n"
+    );
+}
+
+#[test]
+fn comments_start_only_at_the_start_of_a_word() {
+    let script = "echo a # b\necho a#b\necho '#x'\nx=abc; echo ${#x}\n";
+    let (stdout, stderr) = run(&[], script);
+    assert_eq!(stdout, "a\na#b\n#x\n3\n");
+    assert_eq!(stderr, "");
+}